@@ -0,0 +1,100 @@
+use super::{ray::Ray, vec::Vector3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub minimum: Vector3,
+    pub maximum: Vector3,
+}
+
+impl Aabb {
+    pub fn new(minimum: Vector3, maximum: Vector3) -> Self {
+        Self { minimum, maximum }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            minimum: Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            maximum: Vector3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn inherit(&mut self, new: Self) {
+        self.minimum = new.minimum;
+        self.maximum = new.maximum;
+    }
+
+    pub fn hit(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for a in 0..3 {
+            let inv_d = 1.0 / r.direction[a];
+            let mut t0 = (self.minimum[a] - r.origin[a]) * inv_d;
+            let mut t1 = (self.maximum[a] - r.origin[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    pub fn surrounding_box(box0: Aabb, box1: Aabb) -> Self {
+        let small = Vector3::new(
+            box0.minimum.x.min(box1.minimum.x),
+            box0.minimum.y.min(box1.minimum.y),
+            box0.minimum.z.min(box1.minimum.z),
+        );
+        let big = Vector3::new(
+            box0.maximum.x.max(box1.maximum.x),
+            box0.maximum.y.max(box1.maximum.y),
+            box0.maximum.z.max(box1.maximum.z),
+        );
+        return Self::new(small, big);
+    }
+
+    pub fn include_point(&self, p: Vector3) -> Self {
+        return Self::surrounding_box(*self, Self::new(p, p));
+    }
+
+    pub fn pad(&self, delta: f64) -> Self {
+        let mut minimum = self.minimum;
+        let mut maximum = self.maximum;
+        if maximum.x - minimum.x < delta {
+            minimum.x -= delta / 2.0;
+            maximum.x += delta / 2.0;
+        }
+        if maximum.y - minimum.y < delta {
+            minimum.y -= delta / 2.0;
+            maximum.y += delta / 2.0;
+        }
+        if maximum.z - minimum.z < delta {
+            minimum.z -= delta / 2.0;
+            maximum.z += delta / 2.0;
+        }
+        return Self::new(minimum, maximum);
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        return (self.minimum + self.maximum) * 0.5;
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        return 2.0 * (d.x * d.y + d.y * d.z + d.z * d.x);
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.maximum - self.minimum;
+        if d.x > d.y && d.x > d.z {
+            return 0;
+        } else if d.y > d.z {
+            return 1;
+        }
+        return 2;
+    }
+}
@@ -0,0 +1,52 @@
+// Convergence is only checked every few samples, the estimate is too noisy
+// to be trusted sample by sample.
+pub const ADAPTIVE_BATCH: u32 = 4;
+
+// Welford's online mean and variance of the luminance of a pixel's samples.
+#[derive(Debug, Clone, Copy)]
+pub struct RunningVariance {
+    pub count: u32,
+    pub mean: f64,
+    pub m2: f64,
+}
+
+impl Default for RunningVariance {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl RunningVariance {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        return self.m2 / (self.count - 1) as f64;
+    }
+
+    // True once the 95% confidence interval of the mean is within
+    // `threshold` of the mean itself. The small floor stops near black
+    // pixels from chasing an ever shrinking target.
+    pub fn converged(&self, threshold: f64) -> bool {
+        if self.count < 2 {
+            return false;
+        }
+        let error = 1.96 * (self.variance() / self.count as f64).sqrt();
+        return error <= threshold * self.mean.max(0.01);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Deserialize;
+
+use super::{
+    hittable::HitRecord, materials::Material, math::mix_seed, output::ColorTransform, ray::Ray,
+    vec::Vector3,
+};
+
+// Arbitrary output variables, per pixel data about the first surface seen
+// through each pixel that is written alongside the beauty image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum Aov {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    MaterialId,
+    ObjectId,
+    // Number of samples taken, a heatmap in 8-bit images.
+    Samples,
+}
+
+// Data of a single pass, ids use zero for pixels where nothing was hit.
+pub enum AovBuffer {
+    Color(Vec<Vector3>),
+    Scalar(Vec<f64>),
+    Id(Vec<u32>),
+}
+
+impl AovBuffer {
+    // Spreads scalars and ids over all three channels for color formats.
+    pub fn to_colors(&self) -> Vec<Vector3> {
+        return match self {
+            AovBuffer::Color(colors) => colors.clone(),
+            AovBuffer::Scalar(values) => values.iter().map(|x| Vector3::new(*x, *x, *x)).collect(),
+            AovBuffer::Id(ids) => ids
+                .iter()
+                .map(|id| Vector3::new(*id as f64, *id as f64, *id as f64))
+                .collect(),
+        };
+    }
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Samples => "samples",
+        };
+    }
+
+    pub fn extract(&self, samples: &[AovSample]) -> AovBuffer {
+        let collect = |f: fn(&AovSample) -> Vector3| samples.iter().map(f).collect();
+        return match self {
+            Aov::Depth => AovBuffer::Scalar(samples.iter().map(|sample| sample.depth).collect()),
+            Aov::Normal => AovBuffer::Color(collect(|sample| sample.normal)),
+            Aov::Albedo => AovBuffer::Color(collect(|sample| sample.albedo)),
+            Aov::Position => AovBuffer::Color(collect(|sample| sample.position)),
+            Aov::MaterialId => {
+                AovBuffer::Id(samples.iter().map(|sample| sample.material_id).collect())
+            }
+            Aov::ObjectId => AovBuffer::Id(samples.iter().map(|sample| sample.object_id).collect()),
+            Aov::Samples => AovBuffer::Id(samples.iter().map(|sample| sample.samples).collect()),
+        };
+    }
+
+    // Display referred version of the pass for 8-bit images. Depth and
+    // position are normalized over the surfaces in view, ids get a random
+    // but stable color each.
+    pub fn preview(&self, samples: &[AovSample], transform: ColorTransform) -> Vec<Vector3> {
+        let hits = samples.iter().filter(|sample| sample.depth > 0.0);
+        return match self {
+            Aov::Depth => {
+                let (near, far) = hits.fold((f64::INFINITY, 0.0_f64), |(near, far), sample| {
+                    return (near.min(sample.depth), far.max(sample.depth));
+                });
+                let range = (far - near).max(1e-9);
+                samples
+                    .iter()
+                    .map(|sample| {
+                        if sample.depth <= 0.0 {
+                            return Vector3::zero();
+                        }
+                        let gray = 0.1 + 0.9 * (far - sample.depth) / range;
+                        return Vector3::new(gray, gray, gray);
+                    })
+                    .collect()
+            }
+            Aov::Normal => samples
+                .iter()
+                .map(|sample| (sample.normal + Vector3::new(1.0, 1.0, 1.0)) * 0.5)
+                .collect(),
+            Aov::Albedo => samples
+                .iter()
+                .map(|sample| {
+                    let c = sample.albedo;
+                    return Vector3::new(
+                        transform.encode(c.x),
+                        transform.encode(c.y),
+                        transform.encode(c.z),
+                    );
+                })
+                .collect(),
+            Aov::Position => {
+                let mut min = Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+                let mut max = -min;
+                for sample in hits {
+                    for axis in 0..3 {
+                        min[axis] = min[axis].min(sample.position[axis]);
+                        max[axis] = max[axis].max(sample.position[axis]);
+                    }
+                }
+                samples
+                    .iter()
+                    .map(|sample| {
+                        if sample.depth <= 0.0 {
+                            return Vector3::zero();
+                        }
+                        let mut c = Vector3::zero();
+                        for axis in 0..3 {
+                            let extent = max[axis] - min[axis];
+                            if extent > 0.0 {
+                                c[axis] = (sample.position[axis] - min[axis]) / extent;
+                            }
+                        }
+                        return c;
+                    })
+                    .collect()
+            }
+            Aov::MaterialId => samples
+                .iter()
+                .map(|sample| id_color(sample.material_id))
+                .collect(),
+            Aov::ObjectId => samples
+                .iter()
+                .map(|sample| id_color(sample.object_id))
+                .collect(),
+            Aov::Samples => {
+                let most = samples
+                    .iter()
+                    .map(|sample| sample.samples)
+                    .max()
+                    .unwrap_or(0);
+                samples
+                    .iter()
+                    .map(|sample| heat_color(sample.samples as f64 / most.max(1) as f64))
+                    .collect()
+            }
+        };
+    }
+}
+
+fn id_color(id: u32) -> Vector3 {
+    if id == 0 {
+        return Vector3::zero();
+    }
+    let bits = mix_seed(id as u64, 0);
+    let channel = |shift: u32| 0.2 + 0.8 * ((bits >> shift) & 0xff) as f64 / 255.0;
+    return Vector3::new(channel(0), channel(8), channel(16));
+}
+
+// Blue for few samples through green and yellow to red for many.
+fn heat_color(x: f64) -> Vector3 {
+    let stops = [
+        Vector3::new(0.0, 0.0, 0.5),
+        Vector3::new(0.0, 0.4, 1.0),
+        Vector3::new(0.0, 0.9, 0.3),
+        Vector3::new(1.0, 0.9, 0.0),
+        Vector3::new(1.0, 0.1, 0.0),
+    ];
+    let position = x.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let index = (position as usize).min(stops.len() - 2);
+    let t = position - index as f64;
+    return stops[index] * (1.0 - t) + stops[index + 1] * t;
+}
+
+// Pass values for one pixel. Continuous values are averaged over the
+// samples of the pixel that hit something, `hits` of them, ids are taken
+// from the first one. `samples` counts every sample, whether it hit or not.
+#[derive(Debug, Clone, Copy)]
+pub struct AovSample {
+    pub depth: f64,
+    pub normal: Vector3,
+    pub albedo: Vector3,
+    pub position: Vector3,
+    pub material_id: u32,
+    pub object_id: u32,
+    pub hits: u32,
+    pub samples: u32,
+}
+
+impl AovSample {
+    pub fn zero() -> Self {
+        Self {
+            depth: 0.0,
+            normal: Vector3::zero(),
+            albedo: Vector3::zero(),
+            position: Vector3::zero(),
+            material_id: 0,
+            object_id: 0,
+            hits: 0,
+            samples: 0,
+        }
+    }
+
+    // `rec` is the first hit along the camera ray `r`.
+    pub fn from_hit(r: &Ray, rec: &HitRecord, material_ids: &MaterialIds) -> Self {
+        Self {
+            depth: rec.t() * r.direction.length(),
+            normal: rec.normal,
+            albedo: rec.mat.albedo(rec.u, rec.v, rec.p),
+            position: rec.p,
+            material_id: material_ids.get(rec.mat),
+            object_id: rec.object_id,
+            hits: 1,
+            samples: 1,
+        }
+    }
+
+    pub fn accumulate(&mut self, sample: &AovSample) {
+        self.depth += sample.depth;
+        self.normal += sample.normal;
+        self.albedo += sample.albedo;
+        self.position += sample.position;
+        self.hits += sample.hits;
+        if self.material_id == 0 {
+            self.material_id = sample.material_id;
+        }
+        if self.object_id == 0 {
+            self.object_id = sample.object_id;
+        }
+    }
+
+    // Averages over the hits, with the normal brought back to unit length.
+    pub fn mean(&self) -> Self {
+        let mut mean = *self;
+        if self.hits > 0 {
+            let factor = 1.0 / self.hits as f64;
+            mean.depth *= factor;
+            mean.albedo *= factor;
+            mean.position *= factor;
+            if !self.normal.near_zero() {
+                mean.normal = self.normal.unit();
+            }
+        }
+        return mean;
+    }
+}
+
+// Numbers materials in the order they are registered, so ids are stable
+// between renders of the same scene. Materials are told apart by address.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialIds {
+    ids: HashMap<usize, u32>,
+}
+
+impl MaterialIds {
+    pub fn new() -> Self {
+        Self {
+            ids: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, mat: &Arc<dyn Material>) {
+        let next = self.ids.len() as u32 + 1;
+        self.ids.entry(address(mat.as_ref())).or_insert(next);
+    }
+
+    pub fn get(&self, mat: &dyn Material) -> u32 {
+        return self.ids.get(&address(mat)).copied().unwrap_or(0);
+    }
+}
+
+fn address(mat: &dyn Material) -> usize {
+    return mat as *const dyn Material as *const () as usize;
+}
@@ -0,0 +1,564 @@
+use super::{
+    camera::Camera,
+    framebuffer::LightSplats,
+    hittable::{hittable_list::HittableList, HitRecord, Hittable},
+    integrator::{Integrator, PathDepth},
+    onb::Onb,
+    ray::{Background, Ray},
+    sampler::{Decision, PathSampler, Subpath},
+    sampling::{cosine_hemisphere, cosine_hemisphere_pdf},
+    vec::Vector3,
+};
+
+// Bidirectional path tracing after Veach. Every sample traces a subpath
+// from the camera and one from a light, then connects every prefix of one
+// to every prefix of the other. Each connection strategy finds some paths
+// far more easily than the others, such as caustics seen by light paths
+// connected to the camera, and multiple importance sampling weights each
+// by how likely it was to find the path compared to the rest.
+//
+// Densities along the subpaths are kept in area measure, forward in the
+// direction the subpath was traced and in reverse for the opposite one.
+// Materials that cannot be sampled directly are treated as specular: they
+// are never connected through, and their densities drop out of the weights.
+// Depths count the surface vertices of a path, including the one on the
+// light, as the path tracer does.
+pub struct BdptIntegrator {
+    camera: Camera,
+    depth: PathDepth,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex<'a> {
+    kind: VertexKind,
+    p: Vector3,
+    // Surface normal, or the viewing direction at the camera.
+    n: Vector3,
+    rec: HitRecord<'a>,
+    // Ray the subpath arrived along, for material lookups.
+    incoming: Ray,
+    // Throughput of the subpath up to and including this vertex, divided by
+    // its density.
+    beta: Vector3,
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    specular: bool,
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(p: Vector3, forward: Vector3) -> Self {
+        return Self::endpoint(VertexKind::Camera, p, forward, HitRecord::new());
+    }
+
+    fn light(rec: HitRecord<'a>) -> Self {
+        return Self::endpoint(VertexKind::Light, rec.p, rec.normal, rec);
+    }
+
+    fn endpoint(kind: VertexKind, p: Vector3, n: Vector3, rec: HitRecord<'a>) -> Self {
+        Self {
+            kind,
+            p,
+            n,
+            rec,
+            incoming: Ray::zero(),
+            beta: Vector3::new(1.0, 1.0, 1.0),
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            specular: false,
+        }
+    }
+
+    // Scattering towards `next`. Lights emit the same radiance in every
+    // direction, which their `beta` already holds.
+    fn f(&self, next: &Vertex) -> Vector3 {
+        return match self.kind {
+            VertexKind::Surface => self.rec.mat.bsdf(
+                &self.incoming,
+                &self.rec,
+                &Ray::new(self.p, next.p - self.p),
+            ),
+            _ => Vector3::new(1.0, 1.0, 1.0),
+        };
+    }
+
+    fn cos(&self, direction: Vector3) -> f64 {
+        return Vector3::dot(self.n, direction.unit()).abs();
+    }
+
+    fn emitted(&self) -> Vector3 {
+        return self.rec.mat.emitted(self.rec.u, self.rec.v, self.rec.p);
+    }
+}
+
+// Converts a solid angle density of leaving `from` towards `to` into an area
+// density at `to`. The camera is a point, not a surface.
+fn to_area(pdf: f64, from: &Vertex, to: &Vertex) -> f64 {
+    let w = to.p - from.p;
+    let distance_squared = w.length_squared();
+    if distance_squared == 0.0 {
+        return 0.0;
+    }
+    let pdf = pdf / distance_squared;
+    if to.kind == VertexKind::Camera {
+        return pdf;
+    }
+    return pdf * to.cos(w);
+}
+
+// Lights emit from both sides with a cosine distribution around the normal.
+fn emission_pdf(n: Vector3, direction: Vector3) -> f64 {
+    return cosine_hemisphere_pdf(Vector3::dot(n, direction.unit()).abs()) / 2.0;
+}
+
+fn is_black(color: Vector3) -> bool {
+    return color.x == 0.0 && color.y == 0.0 && color.z == 0.0;
+}
+
+fn visible(world: &dyn Hittable, a: Vector3, b: Vector3) -> bool {
+    let w = b - a;
+    let distance = w.length();
+    let mut rec = HitRecord::new();
+    return !world.hit(
+        &Ray::new(a, w / distance),
+        0.001,
+        distance - 0.001,
+        &mut rec,
+    );
+}
+
+// Everything the subpaths and connections of one sample need to know about
+// the scene.
+struct Context<'a> {
+    world: &'a dyn Hittable,
+    lights: &'a HittableList,
+    // Area density of every point on the lights as the start of a light
+    // subpath, zero without lights.
+    light_pdf: f64,
+}
+
+impl BdptIntegrator {
+    pub fn new(camera: Camera, depth: PathDepth, width: u32, height: u32) -> Self {
+        Self {
+            camera,
+            depth,
+            width,
+            height,
+        }
+    }
+
+    // Solid angle density of camera rays along `direction`. Rays are spread
+    // evenly over the image, the film of `Camera::get_ray` covers the pixel
+    // centers.
+    fn camera_pdf(&self, direction: Vector3) -> f64 {
+        let (width, height) = (self.width as f64, self.height as f64);
+        return self.camera.film_density(direction) * (width - 1.0) * (height - 1.0)
+            / (width * height);
+    }
+
+    // Area density at `next` of `vertex` scattering towards it, having been
+    // reached from `prev`.
+    fn pdf(&self, vertex: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.p - vertex.p;
+        let pdf = match (vertex.kind, prev) {
+            (VertexKind::Camera, _) => self.camera_pdf(direction),
+            (VertexKind::Light, _) => emission_pdf(vertex.n, direction),
+            (VertexKind::Surface, Some(prev)) => vertex.rec.mat.pdf(
+                &Ray::new(prev.p, vertex.p - prev.p),
+                &vertex.rec,
+                &Ray::new(vertex.p, direction),
+            ),
+            (VertexKind::Surface, None) => 0.0,
+        };
+        return to_area(pdf, vertex, next);
+    }
+
+    // Extends `path` from its last vertex along `ray`, until it escapes,
+    // hits a light, reaches `max_vertices` or is stopped by Russian
+    // roulette. `pdf` is the solid angle density of `ray`. Returns the ray
+    // and throughput of a path that escaped.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk<'a>(
+        &self,
+        context: &Context<'a>,
+        mut ray: Ray,
+        mut beta: Vector3,
+        mut pdf: f64,
+        max_vertices: usize,
+        sampler: &mut PathSampler,
+        path: &mut Vec<Vertex<'a>>,
+    ) -> Option<(Ray, Vector3)> {
+        while path.len() < max_vertices {
+            let mut rec = HitRecord::new();
+            if !context.world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+                return Some((ray, beta));
+            }
+            sampler.start_vertex();
+
+            let prev = path.len() - 1;
+            let mut vertex = Vertex {
+                kind: VertexKind::Surface,
+                p: rec.p,
+                n: rec.normal,
+                rec,
+                incoming: ray,
+                beta,
+                pdf_fwd: 0.0,
+                pdf_rev: 0.0,
+                specular: false,
+            };
+            vertex.pdf_fwd = to_area(pdf, &path[prev], &vertex);
+            path.push(vertex);
+            if path.len() >= max_vertices {
+                break;
+            }
+
+            let mut scattered = Ray::zero();
+            let mut attenuation = Vector3::zero();
+            if !rec
+                .mat
+                .scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler)
+            {
+                break;
+            }
+            let current = path.len() - 1;
+            pdf = rec.mat.pdf(&ray, &rec, &scattered);
+            if pdf > 0.0 {
+                let reverse = rec.mat.pdf(
+                    &Ray::new(rec.p + scattered.direction, -scattered.direction),
+                    &rec,
+                    &Ray::new(rec.p, -ray.direction),
+                );
+                path[prev].pdf_rev = to_area(reverse, &path[current], &path[prev]);
+            } else {
+                path[current].specular = true;
+            }
+
+            beta = beta * attenuation;
+            ray = scattered;
+            if !self.depth.roulette(current as u32, &mut beta, sampler) {
+                break;
+            }
+        }
+        return None;
+    }
+
+    fn light_subpath<'a>(
+        &self,
+        context: &Context<'a>,
+        sampler: &mut PathSampler,
+        path: &mut Vec<Vertex<'a>>,
+    ) {
+        if context.light_pdf == 0.0 {
+            return;
+        }
+        sampler.start_subpath(Subpath::Light);
+        sampler.start_vertex();
+        sampler.start_decision(Decision::Light);
+        let mut rec = HitRecord::new();
+        if !context.lights.sample_surface(sampler, &mut rec) {
+            return;
+        }
+        let mut light = Vertex::light(rec);
+        let emitted = light.emitted();
+        if is_black(emitted) {
+            return;
+        }
+        light.beta = emitted / context.light_pdf;
+        light.pdf_fwd = context.light_pdf;
+
+        sampler.start_decision(Decision::Scatter);
+        let side = if sampler.get_1d() < 0.5 {
+            light.n
+        } else {
+            -light.n
+        };
+        let (local, _) = cosine_hemisphere(sampler.get_2d());
+        let direction = Onb::build_from_w(side).local(local);
+        let pdf = emission_pdf(light.n, direction);
+        if pdf <= 0.0 {
+            return;
+        }
+        let beta = light.beta * (light.cos(direction) / pdf);
+        path.push(light);
+
+        let max_vertices = match self.depth.max_depth {
+            0 => usize::MAX,
+            max_depth => max_depth as usize,
+        };
+        self.random_walk(
+            context,
+            Ray::new(light.p, direction),
+            beta,
+            pdf,
+            max_vertices,
+            sampler,
+            path,
+        );
+    }
+
+    // Contribution of the path made of the first `s` light and `t` camera
+    // vertices, for `t` of at least two, weighted for the strategy.
+    fn connect<'a>(
+        &self,
+        context: &Context<'a>,
+        light_path: &mut [Vertex<'a>],
+        camera_path: &mut [Vertex<'a>],
+        s: usize,
+        t: usize,
+        sampler: &mut PathSampler,
+    ) -> Vector3 {
+        let pt = camera_path[t - 1];
+        let contribution = match s {
+            // The camera subpath found a light by itself.
+            0 => pt.beta * pt.emitted(),
+            // A light point sampled for `pt` by area, with the same density
+            // as the first vertex of a light subpath.
+            1 => {
+                if pt.specular || context.light_pdf == 0.0 {
+                    return Vector3::zero();
+                }
+                sampler.start_connection(Subpath::Camera, (t - 2) as u32, Decision::Light);
+                let mut rec = HitRecord::new();
+                if !context.lights.sample_surface(sampler, &mut rec) {
+                    return Vector3::zero();
+                }
+                let mut light = Vertex::light(rec);
+                light.pdf_fwd = context.light_pdf;
+                let w = light.p - pt.p;
+                let distance_squared = w.length_squared();
+                if distance_squared == 0.0 {
+                    return Vector3::zero();
+                }
+                let g = pt.cos(w) * light.cos(w) / distance_squared;
+                let contribution =
+                    pt.beta * pt.f(&light) * light.emitted() * (g / context.light_pdf);
+                if is_black(contribution) || !visible(context.world, pt.p, light.p) {
+                    return Vector3::zero();
+                }
+                return contribution * self.mis_weight(context, &mut [light], camera_path, s, t);
+            }
+            _ => {
+                let qs = light_path[s - 1];
+                if pt.specular || qs.specular {
+                    return Vector3::zero();
+                }
+                let w = pt.p - qs.p;
+                let g = qs.cos(w) * pt.cos(w) / w.length_squared();
+                let contribution = qs.beta * qs.f(&pt) * pt.f(&qs) * pt.beta * g;
+                if is_black(contribution) || !visible(context.world, qs.p, pt.p) {
+                    return Vector3::zero();
+                }
+                contribution
+            }
+        };
+        if is_black(contribution) {
+            return Vector3::zero();
+        }
+        return contribution * self.mis_weight(context, light_path, camera_path, s, t);
+    }
+
+    // Connects the light subpath of `s` vertices straight to a point on the
+    // lens, splatting the contribution wherever it lands on the film.
+    fn splat_to_camera(
+        &self,
+        context: &Context,
+        light_path: &mut [Vertex],
+        s: usize,
+        sampler: &mut PathSampler,
+        light_splats: &LightSplats,
+    ) {
+        let qs = light_path[s - 1];
+        if qs.specular {
+            return;
+        }
+        sampler.start_connection(Subpath::Light, (s - 1) as u32, Decision::Lens);
+        let lens = self.camera.lens_point(sampler.get_2d());
+        let direction = qs.p - lens;
+        let Some((u, v)) = self.camera.film_position(lens, direction) else {
+            return;
+        };
+        let (width, height) = (self.width as f64, self.height as f64);
+        let x = u * (width - 1.0);
+        let y = v * (height - 1.0);
+        if !(0.0..width).contains(&x) || !(0.0..height).contains(&y) {
+            return;
+        }
+
+        let camera = Vertex::camera(lens, self.camera.forward());
+        let pdf = self.camera_pdf(direction);
+        let contribution =
+            qs.beta * qs.f(&camera) * (qs.cos(direction) * pdf / direction.length_squared());
+        if is_black(contribution) || !visible(context.world, qs.p, lens) {
+            return;
+        }
+        let weight = self.mis_weight(context, light_path, &mut [camera], s, 1);
+        // `y` counts rows upwards, image space downwards.
+        light_splats.add(x, height - y, contribution * weight);
+    }
+
+    // Power heuristic weight of strategy (`s`, `t`) for its path, against
+    // every other strategy that could have found it. The weight follows from
+    // ratios of the densities along the path, with the densities around the
+    // connection updated for it. Those vertices are changed in place and
+    // restored before returning.
+    fn mis_weight(
+        &self,
+        context: &Context,
+        light: &mut [Vertex],
+        camera: &mut [Vertex],
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let pt = camera[t - 1];
+        let pt_minus = (t > 1).then(|| camera[t - 2]);
+        let qs = (s > 0).then(|| light[s - 1]);
+        let qs_minus = (s > 1).then(|| light[s - 2]);
+
+        if s == 0 {
+            // Only lights can start light subpaths, anything else that
+            // emits is found by camera subpaths alone.
+            let Some(pt_minus) = pt_minus else {
+                return 1.0;
+            };
+            if context.light_pdf == 0.0
+                || context.lights.pdf_value(pt_minus.p, pt.p - pt_minus.p) <= 0.0
+            {
+                return 1.0;
+            }
+        }
+
+        camera[t - 1].pdf_rev = match qs {
+            Some(qs) => self.pdf(&qs, qs_minus.as_ref(), &pt),
+            None => context.light_pdf,
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].pdf_rev = match qs {
+                Some(qs) => self.pdf(&pt, Some(&qs), &pt_minus),
+                None => to_area(emission_pdf(pt.n, pt_minus.p - pt.p), &pt, &pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light[s - 1].pdf_rev = self.pdf(&pt, pt_minus.as_ref(), &qs);
+        }
+        if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+            light[s - 2].pdf_rev = self.pdf(&qs, Some(&pt), &qs_minus);
+        }
+        camera[t - 1].specular = false;
+        if s > 0 {
+            light[s - 1].specular = false;
+        }
+
+        // Specular vertices have no density, their ratio is one.
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].pdf_rev) / remap(camera[i].pdf_fwd);
+            if !camera[i].specular && !camera[i - 1].specular {
+                sum += ratio * ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+            if !light[i].specular && (i == 0 || !light[i - 1].specular) {
+                sum += ratio * ratio;
+            }
+        }
+
+        camera[t - 1] = pt;
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2] = pt_minus;
+        }
+        if let Some(qs) = qs {
+            light[s - 1] = qs;
+        }
+        if let Some(qs_minus) = qs_minus {
+            light[s - 2] = qs_minus;
+        }
+        return 1.0 / (1.0 + sum);
+    }
+}
+
+impl Integrator for BdptIntegrator {
+    fn radiance<'a>(
+        &self,
+        r: &Ray,
+        world: &'a dyn Hittable,
+        lights: &'a HittableList,
+        background: &Background,
+        sampler: &mut PathSampler,
+        light_splats: &LightSplats,
+        first_hit: &mut HitRecord<'a>,
+    ) -> Vector3 {
+        let light_area = lights.area();
+        let context = Context {
+            world,
+            lights,
+            light_pdf: if light_area > 0.0 {
+                1.0 / light_area
+            } else {
+                0.0
+            },
+        };
+        let mut radiance = Vector3::zero();
+
+        sampler.start_subpath(Subpath::Camera);
+        let mut camera_path = vec![Vertex::camera(r.origin, self.camera.forward())];
+        let max_vertices = match self.depth.max_depth {
+            0 => usize::MAX,
+            max_depth => max_depth as usize + 1,
+        };
+        let escaped = self.random_walk(
+            &context,
+            *r,
+            Vector3::new(1.0, 1.0, 1.0),
+            self.camera_pdf(r.direction),
+            max_vertices,
+            sampler,
+            &mut camera_path,
+        );
+        // Only camera subpaths can find the background.
+        if let Some((ray, beta)) = escaped {
+            radiance += beta * background.color(&ray);
+        }
+        if let Some(vertex) = camera_path.get(1) {
+            first_hit.inherit(vertex.rec);
+        }
+
+        let mut light_path = vec![];
+        self.light_subpath(&context, sampler, &mut light_path);
+        light_splats.add_path();
+
+        // Light points for `s` of one are sampled during the connection, so
+        // they do not need a light subpath. A light point seen straight from
+        // the camera is left to the camera subpath.
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len().max(1) {
+                if s + t < 2 || (s == 1 && t == 1) || !self.depth.allows((s + t - 2) as u32) {
+                    continue;
+                }
+                if t == 1 {
+                    self.splat_to_camera(&context, &mut light_path, s, sampler, light_splats);
+                } else {
+                    radiance +=
+                        self.connect(&context, &mut light_path, &mut camera_path, s, t, sampler);
+                }
+            }
+        }
+        return radiance;
+    }
+}
@@ -0,0 +1,129 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+
+// Wall clock time a render may take, written as a sum of hours, minutes and
+// seconds such as `1h30m`, `10m` or `90s`. A bare number counts seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeLimit(pub Duration);
+
+impl FromStr for TimeLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid time limit `{}`, expected a duration like 90s, 10m or 1h30m",
+                s
+            )
+        };
+        let mut rest = s.trim();
+        if let Ok(seconds) = rest.parse::<f64>() {
+            return seconds_to_limit(seconds).ok_or_else(invalid);
+        }
+
+        let mut seconds = 0.0;
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        // Components may be separated by whitespace, as in `1h 30m`.
+        while !rest.is_empty() {
+            let split = rest
+                .find(|c: char| c.is_ascii_alphabetic())
+                .ok_or_else(invalid)?;
+            let value = rest[..split]
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|value| *value >= 0.0)
+                .ok_or_else(invalid)?;
+            let unit_length = rest[split..]
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len() - split);
+            let scale = match &rest[split..split + unit_length] {
+                "h" => 3600.0,
+                "m" | "min" => 60.0,
+                "s" => 1.0,
+                _ => return Err(invalid()),
+            };
+            seconds += value * scale;
+            rest = rest[split + unit_length..].trim_start();
+        }
+        return seconds_to_limit(seconds).ok_or_else(invalid);
+    }
+}
+
+impl TryFrom<String> for TimeLimit {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return s.parse();
+    }
+}
+
+impl fmt::Display for TimeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.0.as_secs();
+        let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+        if hours > 0 {
+            write!(f, "{}h", hours)?;
+        }
+        if minutes > 0 {
+            write!(f, "{}m", minutes)?;
+        }
+        if seconds > 0 || total == 0 {
+            write!(f, "{}s", seconds)?;
+        }
+        return Ok(());
+    }
+}
+
+fn seconds_to_limit(seconds: f64) -> Option<TimeLimit> {
+    if !(seconds > 0.0 && seconds.is_finite()) {
+        return None;
+    }
+    return Some(TimeLimit(Duration::from_secs_f64(seconds)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(s: &str) -> Result<f64, String> {
+        return s.parse::<TimeLimit>().map(|limit| limit.0.as_secs_f64());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(seconds("90s"), Ok(90.0));
+        assert_eq!(seconds("10m"), Ok(600.0));
+        assert_eq!(seconds("10min"), Ok(600.0));
+        assert_eq!(seconds("1h30m"), Ok(5400.0));
+        assert_eq!(seconds("1h 30m"), Ok(5400.0));
+        assert_eq!(seconds(" 1h 30m 15s "), Ok(5415.0));
+        assert_eq!(seconds("1.5h"), Ok(5400.0));
+        assert_eq!(seconds("45"), Ok(45.0));
+        assert_eq!(seconds("2.5"), Ok(2.5));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for s in [
+            "5ms", "", " ", "0", "0s", "-5s", "h", "10x", "1h30", "1h m", "inf",
+        ] {
+            assert!(seconds(s).is_err(), "`{}` was accepted", s);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["90s", "10m", "1h30m", "2h", "1h5s", "3h2m1s"] {
+            let limit: TimeLimit = s.parse().unwrap();
+            assert_eq!(limit.to_string().parse::<TimeLimit>(), Ok(limit));
+        }
+        assert_eq!(TimeLimit(Duration::from_secs(90)).to_string(), "1m30s");
+        assert_eq!(TimeLimit(Duration::from_secs(5400)).to_string(), "1h30m");
+        assert_eq!(TimeLimit(Duration::from_secs(7201)).to_string(), "2h1s");
+    }
+}
@@ -0,0 +1,108 @@
+use super::{
+    math::degrees_to_radians, ray::Ray, sampler::PathSampler, sampling::concentric_disk,
+    vec::Vector3,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    origin: Vector3,
+    lower_left_corner: Vector3,
+    horizontal: Vector3,
+    vertical: Vector3,
+    u: Vector3,
+    v: Vector3,
+    w: Vector3,
+    focus_dist: f64,
+    lens_radius: f64,
+}
+
+impl Camera {
+    pub fn new(
+        lookfrom: &Vector3,
+        lookat: &Vector3,
+        vup: &Vector3,
+        vfov: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_dist: f64,
+    ) -> Self {
+        let theta = degrees_to_radians(vfov);
+        let h = (theta / 2.0).tan();
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let w = (*lookfrom - *lookat).unit();
+        let u = Vector3::cross(*vup, w).unit();
+        let v = Vector3::cross(w, u);
+
+        let origin = *lookfrom;
+        let horizontal = u * viewport_width * focus_dist;
+        let vertical = v * viewport_height * focus_dist;
+        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w * focus_dist;
+
+        let lens_radius = aperture / 2.0;
+        Self {
+            origin,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            w,
+            focus_dist,
+            lens_radius,
+        }
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut PathSampler) -> Ray {
+        let offset = self.lens_offset(sampler.get_2d());
+        return Ray {
+            origin: self.origin + offset,
+            direction: self.lower_left_corner + self.horizontal * s + self.vertical * t
+                - self.origin
+                - offset,
+        };
+    }
+
+    // Lens point for a uniform sample `u`, the same that `get_ray` picks.
+    pub fn lens_point(&self, u: (f64, f64)) -> Vector3 {
+        return self.origin + self.lens_offset(u);
+    }
+
+    fn lens_offset(&self, u: (f64, f64)) -> Vector3 {
+        let (disk, _) = concentric_disk(u);
+        let rd = disk * self.lens_radius;
+        return self.u * rd.x + self.v * rd.y;
+    }
+
+    pub fn forward(&self) -> Vector3 {
+        return -self.w;
+    }
+
+    // The (s, t) at which `get_ray` gives the ray leaving `lens_point` along
+    // `direction`, or nothing if it points away from the film.
+    pub fn film_position(&self, lens_point: Vector3, direction: Vector3) -> Option<(f64, f64)> {
+        let cos_theta = Vector3::dot(direction, self.forward());
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let focus_point = lens_point + direction * (self.focus_dist / cos_theta);
+        let offset = focus_point - self.lower_left_corner;
+        return Some((
+            Vector3::dot(offset, self.horizontal) / self.horizontal.length_squared(),
+            Vector3::dot(offset, self.vertical) / self.vertical.length_squared(),
+        ));
+    }
+
+    // Area of (s, t) space per solid angle of ray directions around
+    // `direction`, the density of `get_ray` directions for uniform s and t
+    // in [0, 1].
+    pub fn film_density(&self, direction: Vector3) -> f64 {
+        let cos_theta = Vector3::dot(direction.unit(), self.forward());
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        let film_area = self.horizontal.length() * self.vertical.length();
+        return self.focus_dist * self.focus_dist / (film_area * cos_theta.powi(3));
+    }
+}
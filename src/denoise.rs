@@ -0,0 +1,147 @@
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::{aov::AovSample, tonemap::luminance, vec::Vector3};
+
+const RADIUS: i64 = 6;
+const SIGMA_SPATIAL: f64 = 3.5;
+const SIGMA_NORMAL: f64 = 0.25;
+const SIGMA_ALBEDO: f64 = 0.08;
+// Relative to the depth of the center pixel.
+const SIGMA_DEPTH: f64 = 0.03;
+// Relative difference of the prefiltered irradiance.
+const SIGMA_COLOR: f64 = 0.6;
+// How much brighter than the mean of its neighbours a pixel may be before it
+// counts as a firefly. Lines and small blobs keep enough bright neighbours to
+// stay below it.
+const FIREFLY_RATIO: f64 = 4.0;
+
+// Cross-bilateral filter guided by the normal, albedo and depth passes.
+// Color is divided by albedo first so texture and material detail survive,
+// only the lighting is smoothed, then multiplied back in. A weak range term
+// on a prefiltered copy of the lighting keeps shadow edges on flat surfaces.
+// Pixels far brighter than all around them are clamped beforehand, the
+// filter would otherwise spread them into blotches.
+pub fn denoise(width: u32, height: u32, pixels: &[Vector3], aovs: &[AovSample]) -> Vec<Vector3> {
+    let (width, height) = (width as i64, height as i64);
+    let index = |x: i64, y: i64| (y * width + x) as usize;
+
+    let irradiance: Vec<Vector3> = pixels
+        .iter()
+        .zip(aovs)
+        .map(|(color, aov)| demodulate(*color, aov))
+        .collect();
+    let irradiance = suppress_fireflies(width, height, &irradiance);
+    let guide = box_blur(width, height, &irradiance);
+
+    return (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let center = &aovs[i as usize];
+            let center_guide = luminance(guide[i as usize]);
+
+            let mut sum = Vector3::zero();
+            let mut total = 0.0;
+            for dy in -RADIUS..=RADIUS {
+                for dx in -RADIUS..=RADIUS {
+                    let (qx, qy) = (x + dx, y + dy);
+                    if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                        continue;
+                    }
+                    let q = index(qx, qy);
+                    let other = &aovs[q];
+
+                    // Never mix surfaces with the background.
+                    if (center.depth > 0.0) != (other.depth > 0.0) {
+                        continue;
+                    }
+                    let mut exponent = (dx * dx + dy * dy) as f64 / (SIGMA_SPATIAL * SIGMA_SPATIAL);
+                    if center.depth > 0.0 {
+                        let normal = (center.normal - other.normal).length_squared();
+                        let albedo = (center.albedo - other.albedo).length_squared();
+                        let depth = (center.depth - other.depth) / (center.depth * SIGMA_DEPTH);
+                        exponent += normal / (SIGMA_NORMAL * SIGMA_NORMAL)
+                            + albedo / (SIGMA_ALBEDO * SIGMA_ALBEDO)
+                            + depth * depth;
+                    }
+                    let other_guide = luminance(guide[q]);
+                    let color = (center_guide - other_guide)
+                        / ((center_guide + other_guide) * SIGMA_COLOR + 1e-4);
+                    exponent += color * color;
+
+                    let weight = (-0.5 * exponent).exp();
+                    sum += irradiance[q] * weight;
+                    total += weight;
+                }
+            }
+            return remodulate(sum / total, center);
+        })
+        .collect();
+}
+
+fn modulation(aov: &AovSample) -> Vector3 {
+    // Background and black surfaces keep their color as is.
+    let channel = |x: f64| if x > 0.01 { x } else { 1.0 };
+    if aov.depth <= 0.0 {
+        return Vector3::new(1.0, 1.0, 1.0);
+    }
+    return Vector3::new(
+        channel(aov.albedo.x),
+        channel(aov.albedo.y),
+        channel(aov.albedo.z),
+    );
+}
+
+fn demodulate(color: Vector3, aov: &AovSample) -> Vector3 {
+    return color / modulation(aov);
+}
+
+fn remodulate(irradiance: Vector3, aov: &AovSample) -> Vector3 {
+    return irradiance * modulation(aov);
+}
+
+// Limits pixels to `FIREFLY_RATIO` times the mean luminance of their
+// neighbours, leaving everything below that untouched.
+fn suppress_fireflies(width: i64, height: i64, pixels: &[Vector3]) -> Vec<Vector3> {
+    return (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let mut sum = 0.0;
+            let mut count = 0.0;
+            for qy in (y - 1).max(0)..=(y + 1).min(height - 1) {
+                for qx in (x - 1).max(0)..=(x + 1).min(width - 1) {
+                    if qx != x || qy != y {
+                        sum += luminance(pixels[(qy * width + qx) as usize]);
+                        count += 1.0;
+                    }
+                }
+            }
+            let color = pixels[i as usize];
+            let l = luminance(color);
+            let limit = FIREFLY_RATIO * sum / count;
+            if l > limit && l > 0.0 {
+                return color * (limit / l);
+            }
+            return color;
+        })
+        .collect();
+}
+
+fn box_blur(width: i64, height: i64, pixels: &[Vector3]) -> Vec<Vector3> {
+    return (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let mut sum = Vector3::zero();
+            let mut count = 0.0;
+            for qy in (y - 1).max(0)..=(y + 1).min(height - 1) {
+                for qx in (x - 1).max(0)..=(x + 1).min(width - 1) {
+                    sum += pixels[(qy * width + qx) as usize];
+                    count += 1.0;
+                }
+            }
+            return sum / count;
+        })
+        .collect();
+}
@@ -0,0 +1,139 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use serde::Deserialize;
+
+// Pixel reconstruction filters. Every sample is splatted to all pixels whose
+// center lies within the radius, given in pixels, weighted by the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Filter {
+    Box(f64),
+    Tent(f64),
+    // Gaussian with a standard deviation of a third of the radius, shifted
+    // down so it reaches zero at the radius.
+    Gaussian(f64),
+    // Mitchell-Netravali with B = C = 1/3.
+    Mitchell(f64),
+    // Lanczos windowed sinc, with as many lobes as the radius has pixels.
+    Lanczos(f64),
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        return Filter::Box(0.5);
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        return match self {
+            Filter::Box(radius)
+            | Filter::Tent(radius)
+            | Filter::Gaussian(radius)
+            | Filter::Mitchell(radius)
+            | Filter::Lanczos(radius) => *radius,
+        };
+    }
+
+    // Weight of a sample at offset (`dx`, `dy`) from a pixel center. Mitchell
+    // and Lanczos have negative lobes.
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        return self.evaluate_1d(dx) * self.evaluate_1d(dy);
+    }
+
+    // Integral of the weights over the plane, which normalizes splats that
+    // are not divided by the weights they add up to.
+    pub fn integral(&self) -> f64 {
+        const STEPS: usize = 1024;
+        let radius = self.radius();
+        let step = 2.0 * radius / STEPS as f64;
+        let line = (0..STEPS)
+            .map(|k| self.evaluate_1d(-radius + (k as f64 + 0.5) * step))
+            .sum::<f64>()
+            * step;
+        return line * line;
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let radius = self.radius();
+        let x = x.abs();
+        if x >= radius {
+            return 0.0;
+        }
+        return match self {
+            Filter::Box(_) => 1.0,
+            Filter::Tent(_) => 1.0 - x / radius,
+            Filter::Gaussian(_) => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell(_) => mitchell(2.0 * x / radius),
+            Filter::Lanczos(_) => sinc(x) * sinc(x / radius),
+        };
+    }
+}
+
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let x2 = x * x;
+    let x3 = x2 * x;
+    if x < 1.0 {
+        return ((12.0 - 9.0 * B - 6.0 * C) * x3
+            + (-18.0 + 12.0 * B + 6.0 * C) * x2
+            + (6.0 - 2.0 * B))
+            / 6.0;
+    }
+    return ((-B - 6.0 * C) * x3
+        + (6.0 * B + 30.0 * C) * x2
+        + (-12.0 * B - 48.0 * C) * x
+        + (8.0 * B + 24.0 * C))
+        / 6.0;
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    return (PI * x).sin() / (PI * x);
+}
+
+// Accepts `box`, `tent`, `gaussian`, `mitchell` and `lanczos`, optionally
+// followed by `:<radius>`.
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        let radius = match value {
+            None => None,
+            Some(value) => match value.parse::<f64>() {
+                Ok(radius) if radius > 0.0 && radius.is_finite() => Some(radius),
+                _ => return Err(format!("invalid filter radius `{}`", value)),
+            },
+        };
+        return match name {
+            "box" => Ok(Filter::Box(radius.unwrap_or(0.5))),
+            "tent" => Ok(Filter::Tent(radius.unwrap_or(1.0))),
+            "gaussian" => Ok(Filter::Gaussian(radius.unwrap_or(1.5))),
+            "mitchell" => Ok(Filter::Mitchell(radius.unwrap_or(2.0))),
+            "lanczos" => Ok(Filter::Lanczos(radius.unwrap_or(3.0))),
+            _ => Err(format!(
+                "unknown filter `{}`, expected box, tent, gaussian, mitchell or lanczos",
+                s
+            )),
+        };
+    }
+}
+
+impl TryFrom<String> for Filter {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return s.parse();
+    }
+}
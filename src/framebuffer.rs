@@ -0,0 +1,577 @@
+use std::{
+    cmp::Ordering,
+    fs, io,
+    path::Path,
+    sync::atomic::{self, AtomicI64, AtomicU64},
+};
+
+use super::{adaptive::RunningVariance, aov::AovSample, filter::Filter, vec::Vector3};
+
+pub const TILE_SIZE: u32 = 32;
+
+// Samples every pixel takes per progressive pass over the image.
+pub const PASS_SAMPLES: u32 = 8;
+
+// Rectangle of the image rendered as one unit of work. `x` and `y` are the
+// top left corner in image space, rows counting downwards.
+#[derive(Debug, Clone, Copy)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        return (self.width * self.height) as usize;
+    }
+
+    // Splits the image into tiles of at most `size` pixels square, ordered in
+    // a spiral from the centre outwards so the interesting part of the image
+    // finishes first.
+    pub fn spiral(width: u32, height: u32, size: u32) -> Vec<Tile> {
+        let columns = width.div_ceil(size);
+        let rows = height.div_ceil(size);
+
+        let mut tiles = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let x = column * size;
+                let y = row * size;
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                });
+            }
+        }
+
+        let center_x = width as f64 / 2.0;
+        let center_y = height as f64 / 2.0;
+        let key = |tile: &Tile| {
+            let dx = (tile.x + tile.width / 2) as f64 - center_x;
+            let dy = (tile.y + tile.height / 2) as f64 - center_y;
+            let ring = (dx.abs().max(dy.abs()) / size as f64).round();
+            return (ring, dy.atan2(dx));
+        };
+        tiles.sort_by(|a, b| {
+            let (ring_a, angle_a) = key(a);
+            let (ring_b, angle_b) = key(b);
+            return ring_a
+                .partial_cmp(&ring_b)
+                .unwrap_or(Ordering::Equal)
+                .then(angle_a.partial_cmp(&angle_b).unwrap_or(Ordering::Equal));
+        });
+        return tiles;
+    }
+}
+
+// Running sums of everything rendered so far for one pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct PixelState {
+    pub color: Vector3,
+    pub stats: RunningVariance,
+    pub aov: AovSample,
+}
+
+impl PixelState {
+    pub fn samples(&self) -> u32 {
+        return self.stats.count;
+    }
+}
+
+// Samples of one tile splatted through the reconstruction filter. The
+// region covers the tile plus the filter radius on every side, clipped to
+// the image, so splats are summed into the framebuffer once per tile.
+#[derive(Debug, Clone)]
+pub struct Splats {
+    filter: Filter,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+    colors: Vec<Vector3>,
+    weights: Vec<f64>,
+}
+
+impl Splats {
+    pub fn new(tile: &Tile, filter: Filter, image_width: u32, image_height: u32) -> Self {
+        let margin = filter.radius().ceil() as i64;
+        let x = (tile.x as i64 - margin).max(0);
+        let y = (tile.y as i64 - margin).max(0);
+        let width = ((tile.x + tile.width) as i64 + margin).min(image_width as i64) - x;
+        let height = ((tile.y + tile.height) as i64 + margin).min(image_height as i64) - y;
+        let size = (width * height) as usize;
+        Self {
+            filter,
+            x,
+            y,
+            width,
+            height,
+            colors: vec![Vector3::zero(); size],
+            weights: vec![0.0; size],
+        }
+    }
+
+    // Adds a sample taken at (`x`, `y`) in image space, rows counting
+    // downwards, to every pixel whose center is within the filter radius.
+    pub fn add(&mut self, x: f64, y: f64, color: Vector3) {
+        // A single broken sample would otherwise spread to its neighbours.
+        if !(color.x.is_finite() && color.y.is_finite() && color.z.is_finite()) {
+            return;
+        }
+        let radius = self.filter.radius();
+        let min_x = ((x - radius - 0.5).ceil() as i64).max(self.x);
+        let max_x = ((x + radius - 0.5).floor() as i64).min(self.x + self.width - 1);
+        let min_y = ((y - radius - 0.5).ceil() as i64).max(self.y);
+        let max_y = ((y + radius - 0.5).floor() as i64).min(self.y + self.height - 1);
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let weight = self
+                    .filter
+                    .evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                if weight != 0.0 {
+                    let index = ((py - self.y) * self.width + px - self.x) as usize;
+                    self.colors[index] += color * weight;
+                    self.weights[index] += weight;
+                }
+            }
+        }
+    }
+}
+
+// Light image sums are kept in fixed point with this many steps per unit.
+const LIGHT_SCALE: f64 = (1u64 << 24) as f64;
+
+// Splats that can land anywhere in the image, from light paths connected
+// straight to the camera. They are weighted by the normalized filter rather
+// than divided by the weights they add up to, and summed in fixed point so
+// the image does not depend on the order the threads add them in. `paths`
+// counts the light paths traced, whether or not they reached the camera.
+pub struct LightSplats<'a> {
+    filter: Filter,
+    normalization: f64,
+    width: i64,
+    height: i64,
+    sums: &'a [AtomicI64],
+    paths: &'a AtomicU64,
+}
+
+impl LightSplats<'_> {
+    pub fn add_path(&self) {
+        self.paths.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    // Adds a sample at (`x`, `y`) in image space, rows counting downwards.
+    pub fn add(&self, x: f64, y: f64, color: Vector3) {
+        if !(color.x.is_finite() && color.y.is_finite() && color.z.is_finite()) {
+            return;
+        }
+        let radius = self.filter.radius();
+        let min_x = ((x - radius - 0.5).ceil() as i64).max(0);
+        let max_x = ((x + radius - 0.5).floor() as i64).min(self.width - 1);
+        let min_y = ((y - radius - 0.5).ceil() as i64).max(0);
+        let max_y = ((y + radius - 0.5).floor() as i64).min(self.height - 1);
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let weight = self
+                    .filter
+                    .evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                if weight != 0.0 {
+                    let index = 3 * (py * self.width + px) as usize;
+                    let weighted = color * (weight / self.normalization);
+                    for channel in 0..3 {
+                        let value = (weighted[channel] * LIGHT_SCALE).round() as i64;
+                        self.sums[index + channel].fetch_add(value, atomic::Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Preallocated accumulation buffers in row-major order, top row first.
+// `colors` sums the samples taken in each pixel, `filtered` and `weights`
+// the filtered samples splatted onto it, `light` the light splats in fixed
+// point, from `light_paths` light paths. `aovs` is empty unless passes were
+// requested.
+#[derive(Debug)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    colors: Vec<Vector3>,
+    stats: Vec<RunningVariance>,
+    filtered: Vec<Vector3>,
+    weights: Vec<f64>,
+    light: Vec<AtomicI64>,
+    light_paths: AtomicU64,
+    aovs: Vec<AovSample>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32, with_aovs: bool) -> Self {
+        let size = (width * height) as usize;
+        Self {
+            width,
+            height,
+            colors: vec![Vector3::zero(); size],
+            stats: vec![RunningVariance::new(); size],
+            filtered: vec![Vector3::zero(); size],
+            weights: vec![0.0; size],
+            light: (0..3 * size).map(|_| AtomicI64::new(0)).collect(),
+            light_paths: AtomicU64::new(0),
+            aovs: if with_aovs {
+                vec![AovSample::zero(); size]
+            } else {
+                vec![]
+            },
+        }
+    }
+
+    pub fn has_aovs(&self) -> bool {
+        return !self.aovs.is_empty();
+    }
+
+    pub fn state(&self, x: u32, y: u32) -> PixelState {
+        let index = (y * self.width + x) as usize;
+        return PixelState {
+            color: self.colors[index],
+            stats: self.stats[index],
+            aov: self.aovs.get(index).copied().unwrap_or(AovSample::zero()),
+        };
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        return self.stats.iter().map(|stats| stats.count as u64).sum();
+    }
+
+    pub fn light_splats(&self, filter: Filter) -> LightSplats<'_> {
+        return LightSplats {
+            filter,
+            normalization: filter.integral(),
+            width: self.width as i64,
+            height: self.height as i64,
+            sums: &self.light,
+            paths: &self.light_paths,
+        };
+    }
+
+    // Filtered radiance of every pixel. Pixels whose weights cancel out,
+    // possible with negative filter lobes, fall back to their mean sample.
+    // Light splats are divided by the average number of light paths per
+    // pixel, which only matches the samples if all of them were traced
+    // bidirectionally.
+    pub fn pixels(&self) -> Vec<Vector3> {
+        let light_scale = match self.light_paths.load(atomic::Ordering::Relaxed) {
+            0 => 0.0,
+            light_paths => self.colors.len() as f64 / light_paths as f64 / LIGHT_SCALE,
+        };
+        return (0..self.colors.len())
+            .map(|index| {
+                let light = Vector3::new(
+                    self.light_sum(index, 0) as f64,
+                    self.light_sum(index, 1) as f64,
+                    self.light_sum(index, 2) as f64,
+                ) * light_scale;
+                let weight = self.weights[index];
+                if weight > 1e-6 {
+                    return self.filtered[index] / weight + light;
+                }
+                return self.colors[index] / self.stats[index].count.max(1) as f64 + light;
+            })
+            .collect();
+    }
+
+    fn light_sum(&self, index: usize, channel: usize) -> i64 {
+        return self.light[3 * index + channel].load(atomic::Ordering::Relaxed);
+    }
+
+    // Mean pass values of every pixel, empty without passes.
+    pub fn aovs(&self) -> Vec<AovSample> {
+        return self.aovs.iter().map(AovSample::mean).collect();
+    }
+
+    // Stores the states a worker rendered for `tile`, in row-major order.
+    pub fn write_tile(&mut self, tile: &Tile, states: &[PixelState]) {
+        let width = tile.width as usize;
+        for (row, states) in states.chunks_exact(width).enumerate() {
+            let start = (tile.y as usize + row) * self.width as usize + tile.x as usize;
+            for (offset, state) in states.iter().enumerate() {
+                self.colors[start + offset] = state.color;
+                self.stats[start + offset] = state.stats;
+                if !self.aovs.is_empty() {
+                    self.aovs[start + offset] = state.aov;
+                }
+            }
+        }
+    }
+
+    pub fn add_splats(&mut self, splats: &Splats) {
+        for row in 0..splats.height {
+            for column in 0..splats.width {
+                let source = (row * splats.width + column) as usize;
+                let target = ((splats.y + row) * self.width as i64 + splats.x + column) as usize;
+                self.filtered[target] += splats.colors[source];
+                self.weights[target] += splats.weights[source];
+            }
+        }
+    }
+
+    // Writes the buffers, the seed they were rendered with and the
+    // fingerprint of the scene and settings to `path`. The file is replaced
+    // atomically so an interrupted write leaves the previous checkpoint
+    // intact.
+    pub fn save_checkpoint(&self, path: &Path, seed: u64, fingerprint: u64) -> io::Result<()> {
+        let mut data = Vec::new();
+        data.extend_from_slice(CHECKPOINT_MAGIC);
+        data.extend_from_slice(&self.width.to_le_bytes());
+        data.extend_from_slice(&self.height.to_le_bytes());
+        data.extend_from_slice(&seed.to_le_bytes());
+        data.extend_from_slice(&fingerprint.to_le_bytes());
+        data.extend_from_slice(
+            &self
+                .light_paths
+                .load(atomic::Ordering::Relaxed)
+                .to_le_bytes(),
+        );
+        data.push(self.has_aovs() as u8);
+        for index in 0..self.colors.len() {
+            let stats = &self.stats[index];
+            push_vector(&mut data, self.colors[index]);
+            data.extend_from_slice(&stats.count.to_le_bytes());
+            data.extend_from_slice(&stats.mean.to_le_bytes());
+            data.extend_from_slice(&stats.m2.to_le_bytes());
+            push_vector(&mut data, self.filtered[index]);
+            data.extend_from_slice(&self.weights[index].to_le_bytes());
+            for channel in 0..3 {
+                data.extend_from_slice(&self.light_sum(index, channel).to_le_bytes());
+            }
+            if let Some(aov) = self.aovs.get(index) {
+                data.extend_from_slice(&aov.depth.to_le_bytes());
+                push_vector(&mut data, aov.normal);
+                push_vector(&mut data, aov.albedo);
+                push_vector(&mut data, aov.position);
+                data.extend_from_slice(&aov.material_id.to_le_bytes());
+                data.extend_from_slice(&aov.object_id.to_le_bytes());
+                data.extend_from_slice(&aov.hits.to_le_bytes());
+                data.extend_from_slice(&aov.samples.to_le_bytes());
+            }
+        }
+
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, data)?;
+        return fs::rename(&temporary, path);
+    }
+
+    // Reads a checkpoint written by `save_checkpoint`, returning the
+    // buffers, their seed and the fingerprint.
+    pub fn load_checkpoint(path: &Path) -> io::Result<(Self, u64, u64)> {
+        let data = fs::read(path)?;
+        let mut reader = Reader { data: &data };
+        if reader.take(CHECKPOINT_MAGIC.len())? != CHECKPOINT_MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let seed = reader.u64()?;
+        let fingerprint = reader.u64()?;
+        let light_paths = reader.u64()?;
+        let with_aovs = reader.take(1)?[0] != 0;
+
+        let mut framebuffer = Framebuffer::new(width, height, with_aovs);
+        framebuffer.light_paths = AtomicU64::new(light_paths);
+        for index in 0..framebuffer.colors.len() {
+            framebuffer.colors[index] = reader.vector()?;
+            framebuffer.stats[index] = RunningVariance {
+                count: reader.u32()?,
+                mean: reader.f64()?,
+                m2: reader.f64()?,
+            };
+            framebuffer.filtered[index] = reader.vector()?;
+            framebuffer.weights[index] = reader.f64()?;
+            for channel in 0..3 {
+                framebuffer.light[3 * index + channel] = AtomicI64::new(reader.u64()? as i64);
+            }
+            if with_aovs {
+                framebuffer.aovs[index] = AovSample {
+                    depth: reader.f64()?,
+                    normal: reader.vector()?,
+                    albedo: reader.vector()?,
+                    position: reader.vector()?,
+                    material_id: reader.u32()?,
+                    object_id: reader.u32()?,
+                    hits: reader.u32()?,
+                    samples: reader.u32()?,
+                };
+            }
+        }
+        if !reader.data.is_empty() {
+            return Err(invalid("checkpoint has trailing data"));
+        }
+        return Ok((framebuffer, seed, fingerprint));
+    }
+}
+
+const CHECKPOINT_MAGIC: &[u8] = b"SHIMMER-CHECKPOINT-6\n";
+
+fn push_vector(data: &mut Vec<u8>, v: Vector3) {
+    for x in [v.x, v.y, v.z] {
+        data.extend_from_slice(&x.to_le_bytes());
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, message);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < count {
+            return Err(invalid("checkpoint is truncated"));
+        }
+        let (head, tail) = self.data.split_at(count);
+        self.data = tail;
+        return Ok(head);
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        return Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        return Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+    }
+
+    fn vector(&mut self) -> io::Result<Vector3> {
+        return Ok(Vector3::new(self.f64()?, self.f64()?, self.f64()?));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fmt, path::PathBuf};
+
+    fn temporary_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("shimmer-{}-{}", std::process::id(), name));
+    }
+
+    // A 3 by 2 framebuffer with samples, filtered splats, light splats and,
+    // if asked for, passes in every pixel.
+    fn rendered(with_aovs: bool) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2, with_aovs);
+        let tile = Tile {
+            x: 0,
+            y: 0,
+            width: 3,
+            height: 2,
+        };
+        let states: Vec<PixelState> = (0..6)
+            .map(|i| {
+                let value = i as f64 * 0.37 + 0.1;
+                let mut stats = RunningVariance::new();
+                for sample in 0..=i {
+                    stats.add(value + sample as f64 * 0.01);
+                }
+                let mut aov = AovSample::zero();
+                aov.depth = value;
+                aov.normal = Vector3::new(0.0, value, 1.0);
+                aov.albedo = Vector3::new(value, 0.5, 0.25);
+                aov.position = Vector3::new(-value, 2.0, value);
+                aov.material_id = i;
+                aov.object_id = i + 1;
+                aov.hits = i;
+                aov.samples = i + 1;
+                return PixelState {
+                    color: Vector3::new(value, value * 2.0, value * 3.0),
+                    stats,
+                    aov,
+                };
+            })
+            .collect();
+        framebuffer.write_tile(&tile, &states);
+
+        let mut splats = Splats::new(&tile, Filter::default(), 3, 2);
+        splats.add(1.2, 0.7, Vector3::new(0.3, 0.6, 0.9));
+        framebuffer.add_splats(&splats);
+
+        let light_splats = framebuffer.light_splats(Filter::default());
+        light_splats.add(2.5, 1.5, Vector3::new(1.5, 0.25, 0.125));
+        light_splats.add_path();
+        light_splats.add_path();
+        return framebuffer;
+    }
+
+    fn assert_same(loaded: &Framebuffer, original: &Framebuffer) {
+        let debug = |value: &dyn fmt::Debug| format!("{:?}", value);
+        assert_eq!(
+            (loaded.width, loaded.height),
+            (original.width, original.height)
+        );
+        assert_eq!(debug(&loaded.colors), debug(&original.colors));
+        assert_eq!(debug(&loaded.stats), debug(&original.stats));
+        assert_eq!(debug(&loaded.filtered), debug(&original.filtered));
+        assert_eq!(debug(&loaded.weights), debug(&original.weights));
+        assert_eq!(debug(&loaded.light), debug(&original.light));
+        assert_eq!(debug(&loaded.light_paths), debug(&original.light_paths));
+        assert_eq!(debug(&loaded.aovs), debug(&original.aovs));
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        for with_aovs in [false, true] {
+            let original = rendered(with_aovs);
+            assert!(original
+                .light
+                .iter()
+                .any(|sum| sum.load(atomic::Ordering::Relaxed) != 0));
+
+            let path = temporary_path(&format!("round-trip-{}.checkpoint", with_aovs));
+            original.save_checkpoint(&path, 42, 0xfeed_beef).unwrap();
+            let loaded = Framebuffer::load_checkpoint(&path);
+            fs::remove_file(&path).unwrap();
+
+            let (loaded, seed, fingerprint) = loaded.unwrap();
+            assert_eq!(seed, 42);
+            assert_eq!(fingerprint, 0xfeed_beef);
+            assert_eq!(loaded.has_aovs(), with_aovs);
+            assert_same(&loaded, &original);
+        }
+    }
+
+    #[test]
+    fn damaged_checkpoints_are_rejected() {
+        let path = temporary_path("damaged.checkpoint");
+        rendered(true).save_checkpoint(&path, 7, 11).unwrap();
+        let data = fs::read(&path).unwrap();
+
+        let load = |data: &[u8]| {
+            fs::write(&path, data).unwrap();
+            return Framebuffer::load_checkpoint(&path).map(|_| ());
+        };
+        let truncated = load(&data[..data.len() - 1]).unwrap_err();
+        let mut wrong_magic = data.clone();
+        wrong_magic[0] = b'X';
+        let wrong_magic = load(&wrong_magic).unwrap_err();
+        let header_only = load(&data[..CHECKPOINT_MAGIC.len() + 4]).unwrap_err();
+        let mut trailing = data.clone();
+        trailing.push(0);
+        let trailing = load(&trailing).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(truncated.to_string(), "checkpoint is truncated");
+        assert_eq!(header_only.to_string(), "checkpoint is truncated");
+        assert_eq!(wrong_magic.to_string(), "not a checkpoint file");
+        assert_eq!(trailing.to_string(), "checkpoint has trailing data");
+    }
+}
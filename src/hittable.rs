@@ -0,0 +1,1249 @@
+use std::sync::Arc;
+
+use super::{
+    aabb::Aabb,
+    materials::{Lambertian, Material},
+    ray::Ray,
+    sampler::PathSampler,
+    vec::Vector3,
+};
+
+static DEFAULT_MATERIAL: Lambertian = Lambertian {
+    albedo: Vector3 {
+        x: 0.5,
+        y: 0.5,
+        z: 0.5,
+    },
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct HitRecord<'a> {
+    pub p: Vector3,
+    pub normal: Vector3,
+    t: f64,
+    pub u: f64,
+    pub v: f64,
+    pub front_face: bool,
+    pub mat: &'a dyn Material,
+    pub object_id: u32,
+}
+
+impl Default for HitRecord<'_> {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<'a> HitRecord<'a> {
+    pub fn new() -> Self {
+        Self {
+            p: Vector3::zero(),
+            normal: Vector3::zero(),
+            t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: false,
+            mat: &DEFAULT_MATERIAL,
+            object_id: 0,
+        }
+    }
+
+    pub fn t(&self) -> f64 {
+        return self.t;
+    }
+
+    pub fn inherit(&mut self, new: Self) {
+        self.front_face = new.front_face;
+        self.normal = new.normal;
+        self.p = new.p;
+        self.t = new.t;
+        self.u = new.u;
+        self.v = new.v;
+        self.mat = new.mat;
+        self.object_id = new.object_id;
+    }
+}
+
+pub trait Hittable: Send + Sync {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool;
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool;
+
+    // Solid angle density of `random` for directions leaving `origin`.
+    // Only objects that can be sampled as lights need to implement these.
+    fn pdf_value(&self, _origin: Vector3, _direction: Vector3) -> f64 {
+        return 0.0;
+    }
+
+    fn random(&self, _origin: Vector3, _sampler: &mut PathSampler) -> Vector3 {
+        return Vector3::new(1.0, 0.0, 0.0);
+    }
+
+    // Surface area, and a uniformly distributed point on the surface with
+    // its normal and material, for paths that start on lights.
+    fn area(&self) -> f64 {
+        return 0.0;
+    }
+
+    fn sample_surface<'a>(&'a self, _sampler: &mut PathSampler, _rec: &mut HitRecord<'a>) -> bool {
+        return false;
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        return (**self).hit(r, t_min, t_max, rec);
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        return (**self).bounding_box(output_box);
+    }
+
+    fn pdf_value(&self, origin: Vector3, direction: Vector3) -> f64 {
+        return (**self).pdf_value(origin, direction);
+    }
+
+    fn random(&self, origin: Vector3, sampler: &mut PathSampler) -> Vector3 {
+        return (**self).random(origin, sampler);
+    }
+
+    fn area(&self) -> f64 {
+        return (**self).area();
+    }
+
+    fn sample_surface<'a>(&'a self, sampler: &mut PathSampler, rec: &mut HitRecord<'a>) -> bool {
+        return (**self).sample_surface(sampler, rec);
+    }
+}
+
+pub mod sphere {
+    use crate::{
+        aabb::Aabb,
+        materials::Material,
+        onb::Onb,
+        ray::Ray,
+        sampler::PathSampler,
+        sampling::{uniform_cone, uniform_cone_pdf, uniform_sphere},
+        vec::Vector3,
+    };
+
+    use std::{f64::consts::PI, sync::Arc};
+
+    use super::{HitRecord, Hittable};
+
+    #[derive(Debug, Clone)]
+    pub struct Sphere {
+        center: Vector3,
+        radius: f64,
+        mat: Arc<dyn Material>,
+    }
+
+    impl Sphere {
+        pub fn new(center: Vector3, radius: f64, mat: Arc<dyn Material>) -> Self {
+            Self {
+                center,
+                radius,
+                mat,
+            }
+        }
+
+        fn get_sphere_uv(p: Vector3) -> (f64, f64) {
+            let theta = (-p.y).acos();
+            let phi = (-p.z).atan2(p.x) + PI;
+            return (phi / (2.0 * PI), theta / PI);
+        }
+    }
+
+    impl Hittable for Sphere {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            let oc = r.origin - self.center;
+            let a = r.direction.length_squared();
+            let half_b = Vector3::dot(oc, r.direction);
+            let c = oc.length_squared() - self.radius * self.radius;
+            let discriminant = half_b * half_b - a * c;
+
+            if discriminant < 0.0 {
+                return false;
+            }
+            let sqrtd = discriminant.sqrt();
+
+            let mut root = (-half_b - sqrtd) / a;
+            if root < t_min || t_max < root {
+                root = (-half_b + sqrtd) / a;
+                if root < t_min || t_max < root {
+                    return false;
+                }
+            };
+            let t = root;
+            let p = r.at(t);
+            let outward_normal = (p - self.center) / self.radius;
+            let front_face = Vector3::dot(r.direction, outward_normal) < 0.0;
+            let normal = if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            };
+            let (u, v) = Sphere::get_sphere_uv(outward_normal);
+
+            rec.inherit(HitRecord {
+                p,
+                normal,
+                t,
+                u,
+                v,
+                front_face,
+                mat: &*self.mat,
+                object_id: 0,
+            });
+
+            return true;
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            let r = Vector3::new(self.radius, self.radius, self.radius);
+            output_box.inherit(Aabb::new(self.center - r, self.center + r));
+            return true;
+        }
+
+        fn pdf_value(&self, origin: Vector3, direction: Vector3) -> f64 {
+            let mut rec = HitRecord::new();
+            if !self.hit(&Ray::new(origin, direction), 0.001, f64::INFINITY, &mut rec) {
+                return 0.0;
+            }
+
+            let distance_squared = (self.center - origin).length_squared();
+            if distance_squared <= self.radius * self.radius {
+                return 0.0;
+            }
+            let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+            return uniform_cone_pdf(cos_theta_max);
+        }
+
+        // Uniform over the cone the sphere subtends. From inside, where
+        // `pdf_value` is zero, any direction will do.
+        fn random(&self, origin: Vector3, sampler: &mut PathSampler) -> Vector3 {
+            let direction = self.center - origin;
+            let distance_squared = direction.length_squared();
+            if distance_squared <= self.radius * self.radius {
+                return uniform_sphere(sampler.get_2d()).0;
+            }
+            let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+            let (local, _) = uniform_cone(sampler.get_2d(), cos_theta_max);
+            return Onb::build_from_w(direction).local(local);
+        }
+
+        fn area(&self) -> f64 {
+            return 4.0 * PI * self.radius * self.radius;
+        }
+
+        fn sample_surface<'a>(
+            &'a self,
+            sampler: &mut PathSampler,
+            rec: &mut HitRecord<'a>,
+        ) -> bool {
+            let (normal, _) = uniform_sphere(sampler.get_2d());
+            let (u, v) = Sphere::get_sphere_uv(normal);
+            rec.inherit(HitRecord {
+                p: self.center + normal * self.radius,
+                normal,
+                t: 0.0,
+                u,
+                v,
+                front_face: true,
+                mat: &*self.mat,
+                object_id: 0,
+            });
+            return true;
+        }
+    }
+}
+
+pub mod triangle {
+    use std::sync::Arc;
+
+    use crate::{
+        aabb::Aabb,
+        materials::Material,
+        ray::Ray,
+        sampler::PathSampler,
+        sampling::{spherical_triangle, spherical_triangle_area},
+        vec::Vector3,
+    };
+
+    use super::{HitRecord, Hittable};
+
+    // Solid angles within which lights are sampled by solid angle. Outside,
+    // the spherical triangle is too thin or too close to the whole
+    // hemisphere for the warp to be accurate and the area is sampled instead.
+    const MIN_SPHERICAL_AREA: f64 = 3e-4;
+    const MAX_SPHERICAL_AREA: f64 = 6.22;
+
+    // Möller–Trumbore ray/triangle intersection. Returns the ray parameter
+    // and the barycentric coordinates (b1, b2) of the second and third vertex.
+    pub fn intersect(
+        r: &Ray,
+        p0: Vector3,
+        p1: Vector3,
+        p2: Vector3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, f64, f64)> {
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+        let pvec = Vector3::cross(r.direction, edge2);
+        let det = Vector3::dot(edge1, pvec);
+
+        // The determinant scales with the square of the triangle's size, a
+        // fixed epsilon would reject every hit on small triangles. Only a
+        // ray parallel to the plane, or a degenerate triangle, has none.
+        if !det.is_normal() {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = r.origin - p0;
+        let b1 = Vector3::dot(tvec, pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = Vector3::cross(tvec, edge1);
+        let b2 = Vector3::dot(r.direction, qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = Vector3::dot(edge2, qvec) * inv_det;
+        if t < t_min || t_max < t {
+            return None;
+        }
+        return Some((t, b1, b2));
+    }
+
+    pub fn interpolate(a: Vector3, b: Vector3, c: Vector3, b1: f64, b2: f64) -> Vector3 {
+        return a * (1.0 - b1 - b2) + b * b1 + c * b2;
+    }
+
+    // Fills the record for a hit on a triangle with geometric normal `ng`.
+    // When a shading normal is given the geometric normal is flipped to lie in
+    // the same hemisphere, so vertex normals decide which side is the outside.
+    pub fn set_hit_record<'a>(
+        r: &Ray,
+        t: f64,
+        ng: Vector3,
+        shading_normal: Option<Vector3>,
+        (u, v): (f64, f64),
+        mat: &'a dyn Material,
+        rec: &mut HitRecord<'a>,
+    ) {
+        let mut outward_normal = ng;
+        let mut shading = ng;
+        if let Some(ns) = shading_normal {
+            if !ns.near_zero() {
+                shading = ns.unit();
+                if Vector3::dot(outward_normal, shading) < 0.0 {
+                    outward_normal = -outward_normal;
+                }
+            }
+        }
+
+        let front_face = Vector3::dot(r.direction, outward_normal) < 0.0;
+        let normal = if front_face { shading } else { -shading };
+
+        rec.inherit(HitRecord {
+            p: r.at(t),
+            normal,
+            t,
+            u,
+            v,
+            front_face,
+            mat,
+            object_id: 0,
+        });
+    }
+
+    pub fn bounding_box(p0: Vector3, p1: Vector3, p2: Vector3) -> Aabb {
+        return Aabb::new(p0, p0)
+            .include_point(p1)
+            .include_point(p2)
+            .pad(1e-4);
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Triangle {
+        vertices: [Vector3; 3],
+        normals: Option<[Vector3; 3]>,
+        mat: Arc<dyn Material>,
+    }
+
+    impl Triangle {
+        pub fn new(p0: Vector3, p1: Vector3, p2: Vector3, mat: Arc<dyn Material>) -> Self {
+            Self {
+                vertices: [p0, p1, p2],
+                normals: None,
+                mat,
+            }
+        }
+
+        pub fn with_normals(
+            p0: Vector3,
+            p1: Vector3,
+            p2: Vector3,
+            n0: Vector3,
+            n1: Vector3,
+            n2: Vector3,
+            mat: Arc<dyn Material>,
+        ) -> Self {
+            Self {
+                vertices: [p0, p1, p2],
+                normals: Some([n0, n1, n2]),
+                mat,
+            }
+        }
+    }
+
+    impl Hittable for Triangle {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            let [p0, p1, p2] = self.vertices;
+            let (t, b1, b2) = match intersect(r, p0, p1, p2, t_min, t_max) {
+                Some(hit) => hit,
+                None => return false,
+            };
+
+            let ng = Vector3::cross(p1 - p0, p2 - p0).unit();
+            let ns = self
+                .normals
+                .map(|[n0, n1, n2]| interpolate(n0, n1, n2, b1, b2));
+            set_hit_record(r, t, ng, ns, (b1, b2), &*self.mat, rec);
+            return true;
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            let [p0, p1, p2] = self.vertices;
+            output_box.inherit(bounding_box(p0, p1, p2));
+            return true;
+        }
+
+        fn pdf_value(&self, origin: Vector3, direction: Vector3) -> f64 {
+            let mut rec = HitRecord::new();
+            if !self.hit(&Ray::new(origin, direction), 0.001, f64::INFINITY, &mut rec) {
+                return 0.0;
+            }
+
+            let solid_angle = spherical_triangle_area(self.vertices, origin);
+            if (MIN_SPHERICAL_AREA..=MAX_SPHERICAL_AREA).contains(&solid_angle) {
+                return 1.0 / solid_angle;
+            }
+
+            let [p0, p1, p2] = self.vertices;
+            let ng = Vector3::cross(p1 - p0, p2 - p0);
+            let area = ng.length() / 2.0;
+            let distance_squared = rec.t * rec.t * direction.length_squared();
+            let cosine = (Vector3::dot(direction, ng.unit()) / direction.length()).abs();
+
+            return distance_squared / (cosine * area);
+        }
+
+        fn random(&self, origin: Vector3, sampler: &mut PathSampler) -> Vector3 {
+            let u = sampler.get_2d();
+            let solid_angle = spherical_triangle_area(self.vertices, origin);
+            if (MIN_SPHERICAL_AREA..=MAX_SPHERICAL_AREA).contains(&solid_angle) {
+                if let Some((direction, _)) = spherical_triangle(self.vertices, origin, u) {
+                    return direction;
+                }
+            }
+
+            // Uniform barycentric coordinates from the square root warp.
+            let [p0, p1, p2] = self.vertices;
+            let (u1, u2) = u;
+            let su = u1.sqrt();
+            let b1 = 1.0 - su;
+            let b2 = u2 * su;
+            return interpolate(p0, p1, p2, b1, b2) - origin;
+        }
+
+        fn area(&self) -> f64 {
+            let [p0, p1, p2] = self.vertices;
+            return Vector3::cross(p1 - p0, p2 - p0).length() / 2.0;
+        }
+
+        // Lights emit from both sides, so the geometric normal will do.
+        fn sample_surface<'a>(
+            &'a self,
+            sampler: &mut PathSampler,
+            rec: &mut HitRecord<'a>,
+        ) -> bool {
+            let [p0, p1, p2] = self.vertices;
+            let (u1, u2) = sampler.get_2d();
+            let su = u1.sqrt();
+            let b1 = 1.0 - su;
+            let b2 = u2 * su;
+            rec.inherit(HitRecord {
+                p: interpolate(p0, p1, p2, b1, b2),
+                normal: Vector3::cross(p1 - p0, p2 - p0).unit(),
+                t: 0.0,
+                u: b1,
+                v: b2,
+                front_face: true,
+                mat: &*self.mat,
+                object_id: 0,
+            });
+            return true;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn hit(scale: f64) -> Option<(f64, f64, f64)> {
+            let (p0, p1, p2) = (
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(scale, 0.0, 0.0),
+                Vector3::new(0.0, scale, 0.0),
+            );
+            let origin = Vector3::new(0.25 * scale, 0.25 * scale, 1.0);
+            let r = Ray::new(origin, Vector3::new(0.0, 0.0, -0.1));
+            return intersect(&r, p0, p1, p2, 0.0, f64::INFINITY);
+        }
+
+        #[test]
+        fn small_triangles_are_hit() {
+            for scale in [1.0, 1e-3, 1e-6] {
+                let (t, b1, b2) = hit(scale).expect("missed the triangle");
+                assert!((t - 10.0).abs() < 1e-9 && (b1 - 0.25).abs() < 1e-9);
+                assert!((b2 - 0.25).abs() < 1e-9);
+            }
+        }
+
+        #[test]
+        fn parallel_rays_miss() {
+            let r = Ray::new(Vector3::new(0.1, 0.1, 0.0), Vector3::new(1.0, 0.0, 0.0));
+            let (p0, p1, p2) = (
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+            );
+            assert!(intersect(&r, p0, p1, p2, 0.0, f64::INFINITY).is_none());
+        }
+    }
+}
+
+pub mod mesh {
+    use std::sync::Arc;
+
+    use crate::{aabb::Aabb, materials::Material, ray::Ray, vec::Vector3};
+
+    use super::{bvh::BvhNode, triangle, HitRecord, Hittable};
+
+    // Vertex and index buffers shared by every triangle of a mesh. `normals`
+    // and `uvs` are either empty or have one entry per position.
+    #[derive(Debug, Clone)]
+    pub struct MeshData {
+        pub positions: Vec<Vector3>,
+        pub normals: Vec<Vector3>,
+        pub uvs: Vec<(f64, f64)>,
+        pub indices: Vec<[u32; 3]>,
+        pub mat: Arc<dyn Material>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct MeshTriangle {
+        mesh: Arc<MeshData>,
+        index: usize,
+    }
+
+    impl MeshTriangle {
+        fn vertices(&self) -> [usize; 3] {
+            let [i0, i1, i2] = self.mesh.indices[self.index];
+            return [i0 as usize, i1 as usize, i2 as usize];
+        }
+    }
+
+    impl Hittable for MeshTriangle {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            let mesh = &self.mesh;
+            let [i0, i1, i2] = self.vertices();
+            let (p0, p1, p2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+
+            let (t, b1, b2) = match triangle::intersect(r, p0, p1, p2, t_min, t_max) {
+                Some(hit) => hit,
+                None => return false,
+            };
+
+            let ng = Vector3::cross(p1 - p0, p2 - p0).unit();
+            let ns = if mesh.normals.is_empty() {
+                None
+            } else {
+                Some(triangle::interpolate(
+                    mesh.normals[i0],
+                    mesh.normals[i1],
+                    mesh.normals[i2],
+                    b1,
+                    b2,
+                ))
+            };
+            let uv = if mesh.uvs.is_empty() {
+                (b1, b2)
+            } else {
+                let (uv0, uv1, uv2) = (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]);
+                (
+                    uv0.0 * (1.0 - b1 - b2) + uv1.0 * b1 + uv2.0 * b2,
+                    uv0.1 * (1.0 - b1 - b2) + uv1.1 * b1 + uv2.1 * b2,
+                )
+            };
+            triangle::set_hit_record(r, t, ng, ns, uv, &*mesh.mat, rec);
+            return true;
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            let [i0, i1, i2] = self.vertices();
+            let positions = &self.mesh.positions;
+            output_box.inherit(triangle::bounding_box(
+                positions[i0],
+                positions[i1],
+                positions[i2],
+            ));
+            return true;
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct TriangleMesh {
+        bvh: BvhNode<MeshTriangle>,
+        mat: Arc<dyn Material>,
+    }
+
+    impl TriangleMesh {
+        pub fn new(data: MeshData) -> Self {
+            let data = Arc::new(data);
+            let triangles = (0..data.indices.len())
+                .map(|index| MeshTriangle {
+                    mesh: Arc::clone(&data),
+                    index,
+                })
+                .collect();
+            Self {
+                bvh: BvhNode::new(triangles),
+                mat: Arc::clone(&data.mat),
+            }
+        }
+
+        pub fn material(&self) -> &Arc<dyn Material> {
+            return &self.mat;
+        }
+    }
+
+    impl Hittable for TriangleMesh {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            return self.bvh.hit(r, t_min, t_max, rec);
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            return self.bvh.bounding_box(output_box);
+        }
+    }
+}
+
+pub mod quad {
+    use std::sync::Arc;
+
+    use crate::{aabb::Aabb, materials::Material, ray::Ray, sampler::PathSampler, vec::Vector3};
+
+    use super::{hittable_list::HittableList, HitRecord, Hittable};
+
+    // Parallelogram spanned by `u` and `v` from the corner `q`.
+    #[derive(Debug, Clone)]
+    pub struct Quad {
+        q: Vector3,
+        u: Vector3,
+        v: Vector3,
+        w: Vector3,
+        normal: Vector3,
+        d: f64,
+        area: f64,
+        mat: Arc<dyn Material>,
+    }
+
+    impl Quad {
+        pub fn new(q: Vector3, u: Vector3, v: Vector3, mat: Arc<dyn Material>) -> Self {
+            let n = Vector3::cross(u, v);
+            let normal = n.unit();
+            Self {
+                q,
+                u,
+                v,
+                w: n / Vector3::dot(n, n),
+                normal,
+                d: Vector3::dot(normal, q),
+                area: n.length(),
+                mat,
+            }
+        }
+    }
+
+    impl Hittable for Quad {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            let denom = Vector3::dot(self.normal, r.direction);
+            if denom.abs() < 1e-8 {
+                return false;
+            }
+
+            let t = (self.d - Vector3::dot(self.normal, r.origin)) / denom;
+            if t < t_min || t_max < t {
+                return false;
+            }
+
+            let p = r.at(t);
+            let planar = p - self.q;
+            let alpha = Vector3::dot(self.w, Vector3::cross(planar, self.v));
+            let beta = Vector3::dot(self.w, Vector3::cross(self.u, planar));
+            if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+                return false;
+            }
+
+            let front_face = Vector3::dot(r.direction, self.normal) < 0.0;
+            let normal = if front_face {
+                self.normal
+            } else {
+                -self.normal
+            };
+
+            rec.inherit(HitRecord {
+                p,
+                normal,
+                t,
+                u: alpha,
+                v: beta,
+                front_face,
+                mat: &*self.mat,
+                object_id: 0,
+            });
+            return true;
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            let diagonal1 = Aabb::new(self.q, self.q).include_point(self.q + self.u + self.v);
+            let diagonal2 =
+                Aabb::new(self.q + self.u, self.q + self.u).include_point(self.q + self.v);
+            output_box.inherit(Aabb::surrounding_box(diagonal1, diagonal2).pad(1e-4));
+            return true;
+        }
+
+        fn pdf_value(&self, origin: Vector3, direction: Vector3) -> f64 {
+            let mut rec = HitRecord::new();
+            if !self.hit(&Ray::new(origin, direction), 0.001, f64::INFINITY, &mut rec) {
+                return 0.0;
+            }
+
+            let distance_squared = rec.t * rec.t * direction.length_squared();
+            let cosine = (Vector3::dot(direction, rec.normal) / direction.length()).abs();
+
+            return distance_squared / (cosine * self.area);
+        }
+
+        fn random(&self, origin: Vector3, sampler: &mut PathSampler) -> Vector3 {
+            let (a, b) = sampler.get_2d();
+            let p = self.q + self.u * a + self.v * b;
+            return p - origin;
+        }
+
+        fn area(&self) -> f64 {
+            return self.area;
+        }
+
+        fn sample_surface<'a>(
+            &'a self,
+            sampler: &mut PathSampler,
+            rec: &mut HitRecord<'a>,
+        ) -> bool {
+            let (a, b) = sampler.get_2d();
+            rec.inherit(HitRecord {
+                p: self.q + self.u * a + self.v * b,
+                normal: self.normal,
+                t: 0.0,
+                u: a,
+                v: b,
+                front_face: true,
+                mat: &*self.mat,
+                object_id: 0,
+            });
+            return true;
+        }
+    }
+
+    // The six outward facing sides of the box with opposite corners `a` and `b`.
+    pub fn make_box(a: Vector3, b: Vector3, mat: Arc<dyn Material>) -> HittableList {
+        let mut sides = HittableList::new();
+
+        let min = Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        let dx = Vector3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vector3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vector3::new(0.0, 0.0, max.z - min.z);
+
+        let faces = [
+            (Vector3::new(min.x, min.y, max.z), dx, dy),
+            (Vector3::new(max.x, min.y, max.z), -dz, dy),
+            (Vector3::new(max.x, min.y, min.z), -dx, dy),
+            (Vector3::new(min.x, min.y, min.z), dz, dy),
+            (Vector3::new(min.x, max.y, max.z), dx, -dz),
+            (Vector3::new(min.x, min.y, min.z), dx, dz),
+        ];
+        for (q, u, v) in faces {
+            sides.add(Arc::new(Quad::new(q, u, v, Arc::clone(&mat))));
+        }
+        return sides;
+    }
+}
+
+// Stamps `id` into the records of every hit on `object`, so passes can tell
+// scene objects apart.
+pub mod tagged {
+    use std::sync::Arc;
+
+    use crate::{aabb::Aabb, ray::Ray, sampler::PathSampler, vec::Vector3};
+
+    use super::{HitRecord, Hittable};
+
+    pub struct Tagged {
+        object: Arc<dyn Hittable>,
+        id: u32,
+    }
+
+    impl Tagged {
+        pub fn new(object: Arc<dyn Hittable>, id: u32) -> Self {
+            Self { object, id }
+        }
+    }
+
+    impl Hittable for Tagged {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            if !self.object.hit(r, t_min, t_max, rec) {
+                return false;
+            }
+            rec.object_id = self.id;
+            return true;
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            return self.object.bounding_box(output_box);
+        }
+
+        fn pdf_value(&self, origin: Vector3, direction: Vector3) -> f64 {
+            return self.object.pdf_value(origin, direction);
+        }
+
+        fn random(&self, origin: Vector3, sampler: &mut PathSampler) -> Vector3 {
+            return self.object.random(origin, sampler);
+        }
+
+        fn area(&self) -> f64 {
+            return self.object.area();
+        }
+
+        fn sample_surface<'a>(
+            &'a self,
+            sampler: &mut PathSampler,
+            rec: &mut HitRecord<'a>,
+        ) -> bool {
+            if !self.object.sample_surface(sampler, rec) {
+                return false;
+            }
+            rec.object_id = self.id;
+            return true;
+        }
+    }
+}
+
+pub mod hittable_list {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        aabb::Aabb,
+        materials::{Dielectric, Lambertian, Material, Metal},
+        ray::Ray,
+        sampler::PathSampler,
+        vec::Vector3,
+    };
+
+    use super::{sphere::Sphere, HitRecord, Hittable};
+
+    #[derive(Clone)]
+    pub struct HittableList {
+        objects: Vec<Arc<dyn Hittable>>,
+    }
+
+    impl Default for HittableList {
+        fn default() -> Self {
+            return Self::new();
+        }
+    }
+
+    impl HittableList {
+        pub fn new() -> Self {
+            Self { objects: vec![] }
+        }
+
+        #[allow(dead_code)]
+        pub fn clear(&mut self) {
+            self.objects.clear();
+        }
+
+        pub fn add(&mut self, object: Arc<dyn Hittable>) {
+            self.objects.push(object);
+        }
+
+        pub fn is_empty(&self) -> bool {
+            return self.objects.is_empty();
+        }
+
+        pub fn into_objects(self) -> Vec<Arc<dyn Hittable>> {
+            return self.objects;
+        }
+
+        // Also returns the materials of the spheres, so their ids can be
+        // registered for the material pass.
+        pub fn random_scene(seed: u64) -> (Self, Vec<Arc<dyn Material>>) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut world = HittableList::new();
+            let mut materials = vec![];
+
+            let ground_material: Arc<dyn Material> =
+                Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+            materials.push(Arc::clone(&ground_material));
+            world.add(Arc::new(Sphere::new(
+                Vector3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                ground_material,
+            )));
+
+            for a in -11..11 {
+                for b in -11..11 {
+                    let choose_mat: f64 = rng.gen();
+                    let center = Vector3::new(
+                        a as f64 + 0.9 * rng.gen::<f64>(),
+                        0.2,
+                        b as f64 + 0.9 * rng.gen::<f64>(),
+                    );
+                    let sphere_material: Arc<dyn Material>;
+
+                    if (center - Vector3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                        if choose_mat < 0.8 {
+                            let albedo = Vector3::random(&mut rng) * Vector3::random(&mut rng);
+                            sphere_material = Arc::new(Lambertian::new(albedo));
+                        } else if choose_mat < 0.95 {
+                            let albedo = Vector3::random_bound(&mut rng, 0.5, 1.0);
+                            let fuzz: f64 = rng.gen();
+                            sphere_material = Arc::new(Metal::new(albedo, fuzz));
+                        } else {
+                            sphere_material = Arc::new(Dielectric::new(1.5));
+                        }
+                        materials.push(Arc::clone(&sphere_material));
+                        world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
+                    }
+                }
+            }
+
+            let material1: Arc<dyn Material> = Arc::new(Dielectric::new(1.5));
+            materials.push(Arc::clone(&material1));
+            world.add(Arc::new(Sphere::new(
+                Vector3::new(0.0, 1.0, 0.0),
+                1.0,
+                material1,
+            )));
+
+            let material2: Arc<dyn Material> =
+                Arc::new(Lambertian::new(Vector3::new(0.4, 0.2, 0.1)));
+            materials.push(Arc::clone(&material2));
+            world.add(Arc::new(Sphere::new(
+                Vector3::new(-4.0, 1.0, 0.0),
+                1.0,
+                material2,
+            )));
+
+            let material3: Arc<dyn Material> =
+                Arc::new(Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0));
+            materials.push(Arc::clone(&material3));
+            world.add(Arc::new(Sphere::new(
+                Vector3::new(4.0, 1.0, 0.0),
+                1.0,
+                material3,
+            )));
+
+            return (world, materials);
+        }
+    }
+
+    impl Hittable for HittableList {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            let mut temp_rec = HitRecord::new();
+            let mut hit_anything = false;
+            let mut closest_so_far = t_max;
+
+            for object in self.objects.iter() {
+                if object.hit(r, t_min, closest_so_far, &mut temp_rec) {
+                    hit_anything = true;
+                    closest_so_far = temp_rec.t;
+                    rec.inherit(temp_rec);
+                };
+            }
+            return hit_anything;
+        }
+
+        fn pdf_value(&self, origin: Vector3, direction: Vector3) -> f64 {
+            let weight = 1.0 / self.objects.len() as f64;
+            return self
+                .objects
+                .iter()
+                .map(|object| weight * object.pdf_value(origin, direction))
+                .sum();
+        }
+
+        fn random(&self, origin: Vector3, sampler: &mut PathSampler) -> Vector3 {
+            let count = self.objects.len();
+            let index = ((sampler.get_1d() * count as f64) as usize).min(count - 1);
+            return self.objects[index].random(origin, sampler);
+        }
+
+        fn area(&self) -> f64 {
+            return self.objects.iter().map(|object| object.area()).sum();
+        }
+
+        // Picks objects in proportion to their area, so points are uniformly
+        // distributed over all of them.
+        fn sample_surface<'a>(
+            &'a self,
+            sampler: &mut PathSampler,
+            rec: &mut HitRecord<'a>,
+        ) -> bool {
+            let mut remaining = sampler.get_1d() * self.area();
+            for object in self.objects.iter() {
+                let area = object.area();
+                if remaining < area {
+                    return object.sample_surface(sampler, rec);
+                }
+                remaining -= area;
+            }
+            return match self.objects.iter().rfind(|object| object.area() > 0.0) {
+                Some(object) => object.sample_surface(sampler, rec),
+                None => false,
+            };
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            if self.objects.is_empty() {
+                return false;
+            }
+
+            let mut temp_box = Aabb::empty();
+            let mut first_box = true;
+
+            for object in self.objects.iter() {
+                if !object.bounding_box(&mut temp_box) {
+                    return false;
+                }
+                if first_box {
+                    output_box.inherit(temp_box);
+                } else {
+                    output_box.inherit(Aabb::surrounding_box(*output_box, temp_box));
+                }
+                first_box = false;
+            }
+            return true;
+        }
+    }
+}
+
+pub mod bvh {
+    use crate::{aabb::Aabb, ray::Ray};
+
+    use super::{HitRecord, Hittable};
+
+    const BUCKET_COUNT: usize = 12;
+    const MAX_LEAF_SIZE: usize = 4;
+    // Cost of a traversal step relative to a single primitive intersection.
+    const TRAVERSAL_COST: f64 = 0.125;
+
+    #[derive(Debug, Clone)]
+    pub enum BvhNode<T: Hittable> {
+        Leaf {
+            bbox: Aabb,
+            objects: Vec<T>,
+        },
+        Branch {
+            bbox: Aabb,
+            axis: usize,
+            left: Box<BvhNode<T>>,
+            right: Box<BvhNode<T>>,
+        },
+    }
+
+    impl<T: Hittable> BvhNode<T> {
+        // Objects without a bounding box, such as empty lists, have nothing
+        // to hit and are left out.
+        pub fn new(objects: Vec<T>) -> Self {
+            let primitives = objects
+                .into_iter()
+                .filter_map(|object| {
+                    let mut bbox = Aabb::empty();
+                    return object.bounding_box(&mut bbox).then_some((bbox, object));
+                })
+                .collect();
+            return Self::build(primitives);
+        }
+
+        fn build(mut primitives: Vec<(Aabb, T)>) -> Self {
+            let bbox = primitives
+                .iter()
+                .fold(Aabb::empty(), |acc, p| Aabb::surrounding_box(acc, p.0));
+            let count = primitives.len();
+
+            if count <= 1 {
+                return Self::leaf(bbox, primitives);
+            }
+
+            let centroid_bounds = primitives
+                .iter()
+                .fold(Aabb::empty(), |acc, p| acc.include_point(p.0.centroid()));
+            let axis = centroid_bounds.longest_axis();
+            let axis_min = centroid_bounds.minimum[axis];
+            let extent = centroid_bounds.maximum[axis] - axis_min;
+
+            if extent <= 0.0 {
+                if count <= MAX_LEAF_SIZE {
+                    return Self::leaf(bbox, primitives);
+                }
+                let right = primitives.split_off(count / 2);
+                return Self::branch(bbox, axis, primitives, right);
+            }
+
+            let bucket_of = |b: &Aabb| -> usize {
+                let offset = (b.centroid()[axis] - axis_min) / extent;
+                return ((offset * BUCKET_COUNT as f64) as usize).min(BUCKET_COUNT - 1);
+            };
+
+            let mut counts = [0usize; BUCKET_COUNT];
+            let mut bounds = [Aabb::empty(); BUCKET_COUNT];
+            for p in primitives.iter() {
+                let b = bucket_of(&p.0);
+                counts[b] += 1;
+                bounds[b] = Aabb::surrounding_box(bounds[b], p.0);
+            }
+
+            // Sweep from the right to get the area and count of every suffix,
+            // then from the left to evaluate the SAH cost of each split plane.
+            let mut right_area = [0.0; BUCKET_COUNT];
+            let mut right_count = [0usize; BUCKET_COUNT];
+            let mut acc_box = Aabb::empty();
+            let mut acc_count = 0;
+            for i in (1..BUCKET_COUNT).rev() {
+                acc_box = Aabb::surrounding_box(acc_box, bounds[i]);
+                acc_count += counts[i];
+                right_area[i] = acc_box.surface_area();
+                right_count[i] = acc_count;
+            }
+
+            let parent_area = bbox.surface_area().max(f64::EPSILON);
+            let mut best_split = 0;
+            let mut best_cost = f64::INFINITY;
+            let mut acc_box = Aabb::empty();
+            let mut acc_count = 0;
+            for i in 0..BUCKET_COUNT - 1 {
+                acc_box = Aabb::surrounding_box(acc_box, bounds[i]);
+                acc_count += counts[i];
+                if acc_count == 0 || right_count[i + 1] == 0 {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST
+                    + (acc_count as f64 * acc_box.surface_area()
+                        + right_count[i + 1] as f64 * right_area[i + 1])
+                        / parent_area;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_split = i;
+                }
+            }
+
+            if count <= MAX_LEAF_SIZE && best_cost >= count as f64 {
+                return Self::leaf(bbox, primitives);
+            }
+
+            let (left, right): (Vec<_>, Vec<_>) = primitives
+                .into_iter()
+                .partition(|p| bucket_of(&p.0) <= best_split);
+            return Self::branch(bbox, axis, left, right);
+        }
+
+        fn leaf(bbox: Aabb, primitives: Vec<(Aabb, T)>) -> Self {
+            return BvhNode::Leaf {
+                bbox,
+                objects: primitives.into_iter().map(|p| p.1).collect(),
+            };
+        }
+
+        fn branch(bbox: Aabb, axis: usize, left: Vec<(Aabb, T)>, right: Vec<(Aabb, T)>) -> Self {
+            return BvhNode::Branch {
+                bbox,
+                axis,
+                left: Box::new(Self::build(left)),
+                right: Box::new(Self::build(right)),
+            };
+        }
+    }
+
+    impl<T: Hittable> Hittable for BvhNode<T> {
+        fn hit<'a>(&'a self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+            match self {
+                BvhNode::Leaf { bbox, objects } => {
+                    if !bbox.hit(r, t_min, t_max) {
+                        return false;
+                    }
+                    let mut hit_anything = false;
+                    let mut closest_so_far = t_max;
+
+                    for object in objects.iter() {
+                        if object.hit(r, t_min, closest_so_far, rec) {
+                            hit_anything = true;
+                            closest_so_far = rec.t;
+                        }
+                    }
+                    return hit_anything;
+                }
+                BvhNode::Branch {
+                    bbox,
+                    axis,
+                    left,
+                    right,
+                } => {
+                    if !bbox.hit(r, t_min, t_max) {
+                        return false;
+                    }
+                    // Visit the child nearer to the ray origin first so the far
+                    // one can be culled against the closer hit.
+                    let (first, second) = if r.direction[*axis] < 0.0 {
+                        (right, left)
+                    } else {
+                        (left, right)
+                    };
+                    let hit_first = first.hit(r, t_min, t_max, rec);
+                    let hit_second =
+                        second.hit(r, t_min, if hit_first { rec.t } else { t_max }, rec);
+                    return hit_first || hit_second;
+                }
+            }
+        }
+
+        fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+            match self {
+                BvhNode::Leaf { objects, .. } if objects.is_empty() => return false,
+                BvhNode::Leaf { bbox, .. } | BvhNode::Branch { bbox, .. } => {
+                    output_box.inherit(*bbox);
+                }
+            }
+            return true;
+        }
+    }
+}
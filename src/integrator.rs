@@ -0,0 +1,328 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::{
+    bdpt::BdptIntegrator,
+    camera::Camera,
+    framebuffer::LightSplats,
+    hittable::{hittable_list::HittableList, HitRecord, Hittable},
+    math::power_heuristic,
+    onb::Onb,
+    ray::{Background, Ray},
+    sampler::{Decision, PathSampler},
+    sampling::cosine_hemisphere,
+    scene::RenderSettings,
+    vec::Vector3,
+};
+
+// Light transport algorithms, estimating the radiance that reaches the
+// camera along a ray.
+pub trait Integrator: Send + Sync {
+    // `first_hit` receives the record of the first intersection along `r`,
+    // left untouched if the ray escapes. Radiance that reaches the camera
+    // through other pixels goes to `light_splats`.
+    #[allow(clippy::too_many_arguments)]
+    fn radiance<'a>(
+        &self,
+        r: &Ray,
+        world: &'a dyn Hittable,
+        lights: &'a HittableList,
+        background: &Background,
+        sampler: &mut PathSampler,
+        light_splats: &LightSplats,
+        first_hit: &mut HitRecord<'a>,
+    ) -> Vector3;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum IntegratorKind {
+    // Path tracing that only finds light by hitting it.
+    Naive,
+    // Path tracing with light sampling at every diffuse bounce, weighted
+    // against hitting the light by multiple importance sampling.
+    #[default]
+    Path,
+    // Cosine weighted fraction of the hemisphere above the first hit that
+    // is open, up to the given distance if any.
+    AmbientOcclusion(Option<f64>),
+    // Bidirectional path tracing, connecting camera and light subpaths.
+    Bdpt,
+    // Shading normal, albedo or distance of the first hit.
+    Debug(DebugView),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    Normal,
+    Albedo,
+    Depth,
+}
+
+impl IntegratorKind {
+    pub fn build(&self, settings: &RenderSettings, camera: &Camera) -> Box<dyn Integrator> {
+        let depth = PathDepth {
+            max_depth: settings.max_depth,
+            roulette_depth: settings.roulette_depth,
+        };
+        return match self {
+            IntegratorKind::Naive => Box::new(PathIntegrator {
+                depth,
+                sample_lights: false,
+            }),
+            IntegratorKind::Path => Box::new(PathIntegrator {
+                depth,
+                sample_lights: true,
+            }),
+            IntegratorKind::AmbientOcclusion(distance) => Box::new(AmbientOcclusionIntegrator {
+                distance: distance.unwrap_or(f64::INFINITY),
+            }),
+            IntegratorKind::Bdpt => Box::new(BdptIntegrator::new(
+                *camera,
+                depth,
+                settings.width,
+                settings.height,
+            )),
+            IntegratorKind::Debug(view) => Box::new(DebugIntegrator { view: *view }),
+        };
+    }
+}
+
+// Accepts `naive`, `path`, `bdpt`, `ao` optionally followed by `:<distance>`,
+// `normal`, `albedo` and `depth`.
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(value) = s.strip_prefix("ao:") {
+            return match value.parse::<f64>() {
+                Ok(distance) if distance > 0.0 => {
+                    Ok(IntegratorKind::AmbientOcclusion(Some(distance)))
+                }
+                _ => Err(format!("invalid occlusion distance `{}`", value)),
+            };
+        }
+        return match s {
+            "naive" => Ok(IntegratorKind::Naive),
+            "path" => Ok(IntegratorKind::Path),
+            "bdpt" => Ok(IntegratorKind::Bdpt),
+            "ao" => Ok(IntegratorKind::AmbientOcclusion(None)),
+            "normal" => Ok(IntegratorKind::Debug(DebugView::Normal)),
+            "albedo" => Ok(IntegratorKind::Debug(DebugView::Albedo)),
+            "depth" => Ok(IntegratorKind::Debug(DebugView::Depth)),
+            _ => Err(format!(
+                "unknown integrator `{}`, expected naive, path, bdpt, ao, normal, albedo or depth",
+                s
+            )),
+        };
+    }
+}
+
+impl TryFrom<String> for IntegratorKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return s.parse();
+    }
+}
+
+// Paths end at `max_depth` bounces, if set, and from `roulette_depth` on
+// are continued with a probability that follows their throughput. The
+// survivors are weighted up to make up for the ones that were stopped.
+#[derive(Debug, Clone, Copy)]
+pub struct PathDepth {
+    pub max_depth: u32,
+    pub roulette_depth: u32,
+}
+
+impl PathDepth {
+    pub fn allows(&self, depth: u32) -> bool {
+        return self.max_depth == 0 || depth < self.max_depth;
+    }
+
+    // Decides whether a path continues after `depth` bounces, and weights
+    // its `throughput` accordingly.
+    pub fn roulette(
+        &self,
+        depth: u32,
+        throughput: &mut Vector3,
+        sampler: &mut PathSampler,
+    ) -> bool {
+        if depth < self.roulette_depth {
+            return true;
+        }
+        // Capped below one so paths through glass end too.
+        let survival = throughput.max_component().min(0.95);
+        sampler.start_decision(Decision::Roulette);
+        if sampler.get_1d() >= survival {
+            return false;
+        }
+        *throughput /= survival;
+        return true;
+    }
+}
+
+// Path tracing, with light sampling at every diffuse bounce if
+// `sample_lights` is set. Without it paths only find light by hitting it.
+struct PathIntegrator {
+    depth: PathDepth,
+    sample_lights: bool,
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance<'a>(
+        &self,
+        r: &Ray,
+        world: &'a dyn Hittable,
+        lights: &'a HittableList,
+        background: &Background,
+        sampler: &mut PathSampler,
+        _light_splats: &LightSplats,
+        first_hit: &mut HitRecord<'a>,
+    ) -> Vector3 {
+        let mut radiance = Vector3::zero();
+        let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        // Density with which the previous diffuse bounce chose `ray`, used to
+        // weight emission found along it against light sampling there.
+        let mut bsdf_pdf: Option<f64> = None;
+        let mut depth: u32 = 0;
+
+        while self.depth.allows(depth) {
+            let mut rec = HitRecord::new();
+            if !world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+                radiance += throughput * background.color(&ray);
+                break;
+            }
+            if depth == 0 {
+                first_hit.inherit(rec);
+            }
+            sampler.start_vertex();
+
+            let mut emitted = rec.mat.emitted(rec.u, rec.v, rec.p);
+            if let Some(bsdf_pdf) = bsdf_pdf {
+                if !lights.is_empty() {
+                    let light_pdf = lights.pdf_value(ray.origin, ray.direction);
+                    emitted *= power_heuristic(bsdf_pdf, light_pdf);
+                }
+            }
+            radiance += throughput * emitted;
+
+            let mut scattered = Ray::zero();
+            let mut attenuation = Vector3::zero();
+            if !rec
+                .mat
+                .scatter(&ray, &rec, &mut attenuation, &mut scattered, sampler)
+            {
+                break;
+            }
+
+            // Specular bounces have no density, light sampling cannot help
+            // them. Otherwise only sample lights when the continuation could
+            // still see them.
+            let scatter_pdf = rec.mat.pdf(&ray, &rec, &scattered);
+            let sample_lights = self.sample_lights && scatter_pdf > 0.0;
+            if sample_lights && self.depth.allows(depth + 1) && !lights.is_empty() {
+                sampler.start_decision(Decision::Light);
+                let light_ray = Ray::new(rec.p, lights.random(rec.p, sampler));
+                let light_pdf = lights.pdf_value(rec.p, light_ray.direction);
+                let light_bsdf_pdf = rec.mat.pdf(&ray, &rec, &light_ray);
+
+                let mut light_rec = HitRecord::new();
+                if light_pdf > 0.0
+                    && light_bsdf_pdf > 0.0
+                    && world.hit(&light_ray, 0.001, f64::INFINITY, &mut light_rec)
+                {
+                    let light_emitted =
+                        light_rec.mat.emitted(light_rec.u, light_rec.v, light_rec.p);
+                    let weight = power_heuristic(light_pdf, light_bsdf_pdf);
+                    radiance += throughput
+                        * attenuation
+                        * light_emitted
+                        * (light_bsdf_pdf * weight / light_pdf);
+                }
+            }
+
+            // With `scatter` importance sampling its own lobe, f * cos / pdf
+            // reduces to the attenuation.
+            throughput = throughput * attenuation;
+            bsdf_pdf = sample_lights.then_some(scatter_pdf);
+            ray = scattered;
+            depth += 1;
+            if !self.depth.roulette(depth, &mut throughput, sampler) {
+                break;
+            }
+        }
+        return radiance;
+    }
+}
+
+struct AmbientOcclusionIntegrator {
+    distance: f64,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance<'a>(
+        &self,
+        r: &Ray,
+        world: &'a dyn Hittable,
+        _lights: &'a HittableList,
+        _background: &Background,
+        sampler: &mut PathSampler,
+        _light_splats: &LightSplats,
+        first_hit: &mut HitRecord<'a>,
+    ) -> Vector3 {
+        let mut rec = HitRecord::new();
+        if !world.hit(r, 0.001, f64::INFINITY, &mut rec) {
+            return Vector3::new(1.0, 1.0, 1.0);
+        }
+        first_hit.inherit(rec);
+        sampler.start_vertex();
+
+        // With cosine weighted directions the fraction of rays that escape
+        // estimates the open part of the hemisphere weighted by cosine, the
+        // share of diffuse light from a uniform sky that arrives.
+        let (local, _) = cosine_hemisphere(sampler.get_2d());
+        let direction = Onb::build_from_w(rec.normal).local(local);
+        let occlusion_ray = Ray::new(rec.p, direction);
+        let mut occluder = HitRecord::new();
+        // `t` is measured in units of the direction, which is a unit vector.
+        if world.hit(&occlusion_ray, 0.001, self.distance, &mut occluder) {
+            return Vector3::zero();
+        }
+        return Vector3::new(1.0, 1.0, 1.0);
+    }
+}
+
+struct DebugIntegrator {
+    view: DebugView,
+}
+
+impl Integrator for DebugIntegrator {
+    fn radiance<'a>(
+        &self,
+        r: &Ray,
+        world: &'a dyn Hittable,
+        _lights: &'a HittableList,
+        _background: &Background,
+        _sampler: &mut PathSampler,
+        _light_splats: &LightSplats,
+        first_hit: &mut HitRecord<'a>,
+    ) -> Vector3 {
+        let mut rec = HitRecord::new();
+        if !world.hit(r, 0.001, f64::INFINITY, &mut rec) {
+            return Vector3::zero();
+        }
+        first_hit.inherit(rec);
+        return match self.view {
+            // Components mapped from [-1, 1] to [0, 1].
+            DebugView::Normal => (rec.normal + Vector3::new(1.0, 1.0, 1.0)) * 0.5,
+            DebugView::Albedo => rec.mat.albedo(rec.u, rec.v, rec.p),
+            DebugView::Depth => {
+                let depth = rec.t() * r.direction.length();
+                Vector3::new(depth, depth, depth)
+            }
+        };
+    }
+}
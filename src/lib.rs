@@ -0,0 +1,25 @@
+// Functions end in an explicit `return`, as they have from the start.
+#![allow(clippy::needless_return)]
+
+pub mod aabb;
+pub mod adaptive;
+pub mod aov;
+pub mod bdpt;
+pub mod budget;
+pub mod camera;
+pub mod denoise;
+pub mod filter;
+pub mod framebuffer;
+pub mod hittable;
+pub mod integrator;
+pub mod materials;
+pub mod math;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod ray;
+pub mod sampler;
+pub mod sampling;
+pub mod scene;
+pub mod tonemap;
+pub mod vec;
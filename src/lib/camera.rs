@@ -1,65 +0,0 @@
-use super::{
-    math::{degrees_to_radians, random_in_unit_disk},
-    ray::Ray,
-    vec::Vector3,
-};
-
-#[derive(Debug, Clone, Copy)]
-pub struct Camera {
-    origin: Vector3,
-    lower_left_corner: Vector3,
-    horizontal: Vector3,
-    vertical: Vector3,
-    u: Vector3,
-    v: Vector3,
-    lens_radius: f64,
-}
-
-impl Camera {
-    pub fn new(
-        lookfrom: &Vector3,
-        lookat: &Vector3,
-        vup: &Vector3,
-        vfov: f64,
-        aspect_ratio: f64,
-        aperture: f64,
-        focus_dist: f64,
-    ) -> Self {
-        let theta = degrees_to_radians(vfov);
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
-        let w = (*lookfrom - *lookat).unit();
-        let u = Vector3::cross(*vup, w).unit();
-        let v = Vector3::cross(w, u);
-
-        let origin = *lookfrom;
-        let horizontal = u * viewport_width * focus_dist;
-        let vertical = v * viewport_height * focus_dist;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w * focus_dist;
-
-        let lens_radius = aperture / 2.0;
-        Self {
-            origin,
-            lower_left_corner,
-            horizontal,
-            vertical,
-            u,
-            v,
-            lens_radius,
-        }
-    }
-
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
-        let offset: Vector3 = self.u * rd.x + self.v * rd.y;
-
-        return Ray {
-            origin: self.origin + offset,
-            direction: self.lower_left_corner + self.horizontal * s + self.vertical * t
-                - self.origin
-                - offset,
-        };
-    }
-}
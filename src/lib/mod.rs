@@ -1,7 +0,0 @@
-pub mod camera;
-pub mod hittable;
-pub mod materials;
-pub mod math;
-pub mod output;
-pub mod ray;
-pub mod vec;
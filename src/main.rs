@@ -1,121 +1,600 @@
+// Functions end in an explicit `return`, as they have from the start.
+#![allow(clippy::needless_return)]
+
+use clap::Parser;
 use indicatif::ProgressBar;
-use rand::{rngs::OsRng, Rng};
 use rayon::{
     self,
-    prelude::{IntoParallelRefIterator, ParallelIterator},
+    prelude::{IntoParallelIterator, ParallelIterator},
 };
 use std::{
-    path::Path,
-    sync::{Arc, Mutex},
-    time::Instant,
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
-use crate::lib::{
+use shimmer::{
+    adaptive::ADAPTIVE_BATCH,
+    aov::{Aov, AovBuffer, AovSample, MaterialIds},
+    budget::TimeLimit,
     camera::Camera,
-    hittable::hittable_list::HittableList,
-    output::{push_vector, save_image, Pixel},
-    ray::Ray,
+    denoise::denoise,
+    filter::Filter,
+    framebuffer::{Framebuffer, LightSplats, PixelState, Splats, Tile, PASS_SAMPLES, TILE_SIZE},
+    hittable::{hittable_list::HittableList, HitRecord, Hittable},
+    integrator::{Integrator, IntegratorKind},
+    output::{
+        push_vector, save_exr, save_hdr, save_image, save_pfm, ColorTransform, ExrChannels,
+        ExrPrecision, ImageFormat,
+    },
+    ray::Background,
+    sampler::{PathSampler, Sampler, SamplerKind},
+    scene::{RenderOverrides, RenderSettings, Scene},
+    tonemap::{luminance, ToneMap},
     vec::Vector3,
 };
-mod lib;
+
+/// Ray tracer written in Rust.
+///
+/// Options given here override the `[render]` section of the scene file.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Scene description file; renders the random spheres scene if omitted
+    scene: Option<PathBuf>,
+
+    /// Image width in pixels, keeping the aspect ratio unless --height is given
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Image height in pixels, keeping the aspect ratio unless --width is given
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Samples per pixel, 0 for no limit with --time-limit
+    #[arg(short, long = "spp")]
+    samples: Option<u16>,
+
+    /// Light transport algorithm: path, naive, bdpt, ao, ao:<distance>, or normal,
+    /// albedo or depth to view the first surface hit
+    #[arg(long)]
+    integrator: Option<IntegratorKind>,
+
+    /// Maximum number of bounces per path, 0 for no limit
+    #[arg(short, long)]
+    depth: Option<u32>,
+
+    /// Bounces after which paths are ended at random by Russian roulette
+    #[arg(long)]
+    roulette_depth: Option<u32>,
+
+    /// Pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos,
+    /// optionally followed by :<radius> in pixels
+    #[arg(long)]
+    filter: Option<Filter>,
+
+    /// Sample pattern
+    #[arg(long, value_enum)]
+    sampler: Option<SamplerKind>,
+
+    /// Output image path, .png, .exr, .hdr or .pfm
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Number of render threads, defaults to one per logical core
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Random seed, picked at random and printed if omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Output transfer function: srgb, linear, gamma or gamma:<value>
+    #[arg(long)]
+    transform: Option<ColorTransform>,
+
+    /// Tone mapping operator: none, reinhard, reinhard:<white>, aces or agx
+    #[arg(long)]
+    tone_map: Option<ToneMap>,
+
+    /// Exposure adjustment in stops, applied before tone mapping
+    #[arg(long, allow_negative_numbers = true)]
+    exposure: Option<f64>,
+
+    /// Sample precision of OpenEXR output
+    #[arg(long, value_enum)]
+    exr_precision: Option<ExrPrecision>,
+
+    /// Extra passes to write, comma separated or repeated
+    #[arg(long = "aov", value_enum, value_delimiter = ',')]
+    aovs: Vec<Aov>,
+
+    /// Stop sampling a pixel once its relative error at 95% confidence falls
+    /// below this, taking --spp samples at most
+    #[arg(long)]
+    noise_threshold: Option<f64>,
+
+    /// Samples every pixel takes before --noise-threshold is checked
+    #[arg(long)]
+    min_spp: Option<u16>,
+
+    /// Denoise the image, guided by the normal, albedo and depth passes;
+    /// --denoise=false turns it off
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    denoise: Option<bool>,
+
+    /// With --denoise, also write the noisy image as <output>.noisy.<ext>
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    save_noisy: Option<bool>,
+
+    /// Stop sampling after this long, such as 90s, 10m or 1h30m, and write
+    /// the image rendered so far; denoising and writing come on top
+    #[arg(long)]
+    time_limit: Option<TimeLimit>,
+
+    /// Seconds between checkpoints written to <output>.checkpoint, none are
+    /// written by default
+    #[arg(long)]
+    checkpoint_interval: Option<u64>,
+
+    /// Continue the render in <output>.checkpoint, adding samples up to --spp
+    #[arg(long)]
+    resume: bool,
+}
 
 fn main() {
-    // Image
+    let args = Args::parse();
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
 
-    static ASPECT_RATIO: f64 = 21.0 / 9.0;
-    static IMAGE_WIDTH: u32 = 3440;
-    static IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
-    static SAMPLES_PER_PIXEL: u16 = 100;
-    static MAX_DEPTH: u8 = 5;
+    // Scene
 
-    // World
+    let overrides = RenderOverrides {
+        width: args.width,
+        height: args.height,
+        samples_per_pixel: args.samples,
+        integrator: args.integrator,
+        max_depth: args.depth,
+        roulette_depth: args.roulette_depth,
+        filter: args.filter,
+        sampler: args.sampler,
+        output: args.output.clone(),
+        seed: args.seed,
+        transform: args.transform,
+        tone_map: args.tone_map,
+        exposure: args.exposure,
+        exr_precision: args.exr_precision,
+        aovs: (!args.aovs.is_empty()).then_some(args.aovs.clone()),
+        denoise: args.denoise,
+        save_noisy: args.save_noisy,
+        noise_threshold: args.noise_threshold,
+        min_samples: args.min_spp,
+        checkpoint_interval: args.checkpoint_interval,
+        time_limit: args.time_limit,
+    };
+    let mut scene = load_scene(args.scene.as_deref(), &overrides);
 
-    let world = HittableList::random_scene();
+    // A resumed render has to use the seed of the checkpoint, the random
+    // scene and every pixel's sample streams depend on it.
+    let mut resumed = None;
+    if args.resume {
+        let path = scene.settings.checkpoint_path();
+        let (framebuffer, seed, fingerprint) =
+            Framebuffer::load_checkpoint(&path).unwrap_or_else(|err| {
+                eprintln!("{}: {}", path.display(), err);
+                process::exit(1);
+            });
+        if args.seed.is_some_and(|given| given != seed) {
+            eprintln!(
+                "{}: checkpoint was rendered with seed {}",
+                path.display(),
+                seed
+            );
+            process::exit(1);
+        }
+        if scene.settings.seed() != seed {
+            let overrides = RenderOverrides {
+                seed: Some(seed),
+                ..overrides.clone()
+            };
+            scene = load_scene(args.scene.as_deref(), &overrides);
+        }
+        let settings = &scene.settings;
+        if (framebuffer.width, framebuffer.height) != (settings.width, settings.height) {
+            eprintln!(
+                "{}: checkpoint is {}x{}, not {}x{}",
+                path.display(),
+                framebuffer.width,
+                framebuffer.height,
+                settings.width,
+                settings.height
+            );
+            process::exit(1);
+        }
+        if fingerprint != scene.fingerprint {
+            eprintln!(
+                "{}: checkpoint was rendered from a different scene or with different sampling settings",
+                path.display()
+            );
+            process::exit(1);
+        }
+        if (!settings.aovs.is_empty() || settings.denoise) && !framebuffer.has_aovs() {
+            eprintln!(
+                "{}: checkpoint has no passes, they cannot be added to a resumed render",
+                path.display()
+            );
+            process::exit(1);
+        }
+        resumed = Some(framebuffer);
+    }
 
-    // Camera
+    let settings = &scene.settings;
+    let image_width = settings.width;
+    let image_height = settings.height;
+    let limit = match settings.samples_per_pixel {
+        0 => "unlimited".to_string(),
+        samples_per_pixel => samples_per_pixel.to_string(),
+    };
+    let samples = match settings.noise_threshold {
+        Some(_) => format!("{} to {}", settings.min_samples, limit),
+        None => limit,
+    };
+    let time = match settings.time_limit {
+        Some(time_limit) => format!(" for at most {}", time_limit),
+        None => String::new(),
+    };
 
-    let cam = Camera::new(
-        &Vector3::new(13.0, 2.0, 3.0),
-        &Vector3::new(0.0, 0.0, 0.0),
-        &Vector3::new(0.0, 1.0, 0.0),
-        20.0,
-        ASPECT_RATIO,
-        0.1,
-        10.0,
+    println!(
+        "Rendering {}x{} at {} samples per pixel{} with seed {}.",
+        image_width,
+        image_height,
+        samples,
+        time,
+        settings.seed()
     );
 
     // Render
 
     let begin = Instant::now();
 
-    let bar = ProgressBar::new((IMAGE_HEIGHT * IMAGE_WIDTH).into());
+    // The denoiser is guided by the passes, so keep them for it as well.
+    // Passes in a checkpoint are kept up to date for later resumes.
+    let keep_aovs = !settings.aovs.is_empty()
+        || settings.denoise
+        || resumed.as_ref().is_some_and(Framebuffer::has_aovs);
+    let material_ids = keep_aovs.then_some(&scene.material_ids);
+    let mut framebuffer = resumed
+        .unwrap_or_else(|| Framebuffer::new(image_width, image_height, material_ids.is_some()));
 
-    let data: Arc<Mutex<Vec<Pixel>>> = Arc::new(Mutex::new(vec![]));
+    let pixel_count = (image_width * image_height) as u64;
+    let previous_samples = framebuffer.total_samples();
+    if previous_samples > 0 {
+        println!(
+            "Resuming from {:.1} samples per pixel.",
+            previous_samples as f64 / pixel_count as f64
+        );
+    }
 
-    let jobs = prepare_jobs(IMAGE_HEIGHT, IMAGE_WIDTH);
+    // With a time limit the bar shows the time used in milliseconds, else
+    // the samples taken, if their number is known.
+    let deadline = settings.time_limit.map(|time_limit| begin + time_limit.0);
+    let bar = match (settings.time_limit, settings.samples_per_pixel) {
+        (Some(time_limit), _) => ProgressBar::new(time_limit.0.as_millis() as u64),
+        (None, 0) => ProgressBar::new_spinner(),
+        (None, samples_per_pixel) => {
+            let bar = ProgressBar::new(pixel_count * samples_per_pixel as u64);
+            bar.set_position(previous_samples);
+            bar
+        }
+    };
+    let advance = |samples: u64| match settings.time_limit {
+        Some(_) => bar.set_position(begin.elapsed().as_millis() as u64),
+        None => bar.inc(samples),
+    };
 
-    jobs.par_iter().for_each(|pair| {
-        let pixel = render_pixel(
-            SAMPLES_PER_PIXEL,
-            pair.0,
-            pair.1,
-            IMAGE_WIDTH,
-            IMAGE_HEIGHT,
-            MAX_DEPTH,
-            &cam,
-            &world,
-        );
-        data.lock().unwrap().push(pixel);
-        bar.inc(1);
-    });
+    let sampler = settings
+        .sampler
+        .build(settings.seed(), settings.sample_limit());
+    let integrator = settings.integrator.build(settings, &scene.camera);
+    let tiles = Tile::spiral(image_width, image_height, TILE_SIZE);
+    let checkpoint_path = settings.checkpoint_path();
+    let checkpoint_interval = Duration::from_secs(settings.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
 
-    let output = order_pixels(data, IMAGE_WIDTH, SAMPLES_PER_PIXEL);
-    save_image(Path::new("./image.png"), IMAGE_WIDTH, IMAGE_HEIGHT, &output);
+    // Every pass adds up to PASS_SAMPLES samples to each pixel that still
+    // needs them, until none does or the time is up. Workers pull tiles in
+    // spiral order and render them into their own buckets, which are copied
+    // into the framebuffer once the pass is done. Splats are summed in tile
+    // order, so overlapping ones always add up the same way. Tiles left when
+    // the time runs out keep the samples they have.
+    let out_of_time = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    loop {
+        let next_tile = AtomicUsize::new(0);
+        let light_splats = framebuffer.light_splats(settings.filter);
+        let pass_samples = AtomicU64::new(0);
+        let mut buckets: Vec<(usize, Vec<PixelState>, Splats)> = (0..rayon::current_num_threads())
+            .into_par_iter()
+            .flat_map_iter(|_| {
+                let mut done = vec![];
+                loop {
+                    if out_of_time() {
+                        break;
+                    }
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    let Some(tile) = tiles.get(index) else {
+                        break;
+                    };
+                    let mut states = Vec::with_capacity(tile.pixel_count());
+                    let mut splats = Splats::new(tile, settings.filter, image_width, image_height);
+                    let mut tile_samples = 0;
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            let state = framebuffer.state(x, y);
+                            let rendered = render_pixel(
+                                settings,
+                                x,
+                                image_height - 1 - y,
+                                state,
+                                &mut splats,
+                                &light_splats,
+                                sampler.as_ref(),
+                                integrator.as_ref(),
+                                &scene.camera,
+                                &scene.world,
+                                &scene.lights,
+                                &scene.background,
+                                material_ids,
+                            );
+                            tile_samples += (rendered.samples() - state.samples()) as u64;
+                            states.push(rendered);
+                        }
+                    }
+                    advance(tile_samples);
+                    pass_samples.fetch_add(tile_samples, Ordering::Relaxed);
+                    done.push((index, states, splats));
+                }
+                return done;
+            })
+            .collect();
+
+        if pass_samples.into_inner() == 0 {
+            break;
+        }
+        buckets.sort_by_key(|(index, _, _)| *index);
+        for (index, states, splats) in &buckets {
+            framebuffer.write_tile(&tiles[*index], states);
+            framebuffer.add_splats(splats);
+        }
+        if out_of_time() {
+            break;
+        }
+        if settings.checkpoint_interval > 0 && last_checkpoint.elapsed() >= checkpoint_interval {
+            let saved =
+                framebuffer.save_checkpoint(&checkpoint_path, settings.seed(), scene.fingerprint);
+            exit_on_error(&checkpoint_path, saved);
+            last_checkpoint = Instant::now();
+        }
+    }
 
     bar.finish();
+    // A resumed render keeps its checkpoint current for the next resume.
+    if settings.checkpoint_interval > 0 || args.resume {
+        let saved =
+            framebuffer.save_checkpoint(&checkpoint_path, settings.seed(), scene.fingerprint);
+        exit_on_error(&checkpoint_path, saved);
+    }
+    if settings.noise_threshold.is_some() || settings.time_limit.is_some() {
+        println!(
+            "Took {:.1} samples per pixel on average.",
+            framebuffer.total_samples() as f64 / pixel_count as f64
+        );
+    }
+
+    let mut pixels = framebuffer.pixels();
+    let aovs = framebuffer.aovs();
+    if settings.denoise {
+        if settings.save_noisy {
+            let noisy_path = pass_path(&settings.output, "noisy");
+            let written = write_beauty(&noisy_path, settings, &pixels);
+            exit_on_error(&noisy_path, written);
+        }
+        pixels = denoise(image_width, image_height, &pixels, &aovs);
+    }
+    exit_on_error(&settings.output, write_output(settings, &pixels, &aovs));
     println!("Rendered in {} seconds.", begin.elapsed().as_secs(),);
 }
 
-pub fn order_pixels(data: Arc<Mutex<Vec<Pixel>>>, width: u32, samples: u16) -> Vec<u8> {
-    let mut output: Vec<u8> = Vec::new();
+fn load_scene(path: Option<&Path>, overrides: &RenderOverrides) -> Scene {
+    return match path {
+        Some(path) => Scene::load(path, overrides).map_err(|err| err.to_string()),
+        None => Scene::random(overrides),
+    }
+    .unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+}
 
-    data.lock()
-        .unwrap()
-        .sort_by(|a, b| a.index(width).cmp(&b.index(width)));
+fn write_output(
+    settings: &RenderSettings,
+    pixels: &[Vector3],
+    aovs: &[AovSample],
+) -> io::Result<()> {
+    let path = &settings.output;
+    let (width, height) = (settings.width, settings.height);
+    let format = settings.format();
 
-    for p in data.lock().unwrap().iter() {
-        push_vector(p.color, &mut output, samples);
+    // OpenEXR keeps the passes as layers of the beauty image, every other
+    // format writes one image per pass next to it.
+    if format == ImageFormat::Exr {
+        let buffers: Vec<(Aov, AovBuffer)> = settings
+            .aovs
+            .iter()
+            .map(|aov| (*aov, aov.extract(aovs)))
+            .collect();
+        let mut passes = vec![("", ExrChannels::Color(pixels, ["R", "G", "B"]))];
+        for (aov, buffer) in &buffers {
+            let channels = match (aov, buffer) {
+                (Aov::Albedo, AovBuffer::Color(colors)) => {
+                    ExrChannels::Color(colors, ["R", "G", "B"])
+                }
+                (_, AovBuffer::Color(vectors)) => ExrChannels::Color(vectors, ["X", "Y", "Z"]),
+                (_, AovBuffer::Scalar(values)) => ExrChannels::Scalar(values, "Z"),
+                (_, AovBuffer::Id(ids)) => ExrChannels::Id(ids),
+            };
+            passes.push((aov.name(), channels));
+        }
+        return save_exr(path, width, height, &passes, settings.exr_precision);
     }
 
-    return output;
+    write_beauty(path, settings, pixels)?;
+    for aov in &settings.aovs {
+        let aov_path = pass_path(path, aov.name());
+        match format {
+            ImageFormat::Png => {
+                let preview = aov.preview(aovs, settings.transform);
+                save_png(&aov_path, width, height, &preview, ColorTransform::Linear)?;
+            }
+            ImageFormat::Hdr => {
+                let colors = aov.extract(aovs).to_colors();
+                save_hdr(&aov_path, width, height, &colors)?;
+            }
+            _ => {
+                let colors = aov.extract(aovs).to_colors();
+                save_pfm(&aov_path, width, height, &colors)?;
+            }
+        }
+    }
+    return Ok(());
 }
 
+fn write_beauty(path: &Path, settings: &RenderSettings, pixels: &[Vector3]) -> io::Result<()> {
+    let (width, height) = (settings.width, settings.height);
+    return match settings.format() {
+        ImageFormat::Png => {
+            let mapped = settings.tone_map.apply(pixels, settings.exposure);
+            save_png(path, width, height, &mapped, settings.transform)
+        }
+        // Floating point formats store linear radiance as rendered, without
+        // tone mapping or the output transform.
+        ImageFormat::Exr => {
+            let passes = [("", ExrChannels::Color(pixels, ["R", "G", "B"]))];
+            save_exr(path, width, height, &passes, settings.exr_precision)
+        }
+        ImageFormat::Hdr => save_hdr(path, width, height, pixels),
+        ImageFormat::Pfm => save_pfm(path, width, height, pixels),
+    };
+}
+
+fn exit_on_error(path: &Path, result: io::Result<()>) {
+    if let Err(err) = result {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    }
+}
+
+fn save_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    colors: &[Vector3],
+    transform: ColorTransform,
+) -> io::Result<()> {
+    let mut output = Vec::with_capacity(colors.len() * 3);
+    for color in colors {
+        push_vector(*color, &mut output, transform);
+    }
+    return save_image(path, width, height, &output);
+}
+
+// `image.png` becomes `image.<pass>.png`.
+fn pass_path(path: &Path, pass: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}.{}", stem, pass);
+    if let Some(extension) = path.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    return path.with_file_name(name);
+}
+
+// Adds the next pass of samples to `state`, the samples pixel (i, j) has
+// taken so far, and splats them onto the pixel and its neighbours.
+#[allow(clippy::too_many_arguments)]
 fn render_pixel(
-    samples: u16,
+    settings: &RenderSettings,
     i: u32,
     j: u32,
-    width: u32,
-    height: u32,
-    depth: u8,
+    mut state: PixelState,
+    splats: &mut Splats,
+    light_splats: &LightSplats,
+    sampler: &dyn Sampler,
+    integrator: &dyn Integrator,
     cam: &Camera,
-    world: &HittableList,
-) -> Pixel {
-    let mut pixel_color = Vector3::zero();
-    for _s in 0..samples {
-        let u = (i as f64 + OsRng.gen::<f64>()) / (width - 1) as f64;
-        let v = (j as f64 + OsRng.gen::<f64>()) / (height - 1) as f64;
-        let r = cam.get_ray(u, v);
-        pixel_color += Ray::ray_color(&r, &world, depth);
+    world: &dyn Hittable,
+    lights: &HittableList,
+    background: &Background,
+    material_ids: Option<&MaterialIds>,
+) -> PixelState {
+    let (width, height) = (settings.width, settings.height);
+    let samples_per_pixel = settings.sample_limit();
+    let converged = |state: &PixelState| match settings.noise_threshold {
+        Some(threshold) => {
+            state.samples() >= settings.min_samples as u32 && state.stats.converged(threshold)
+        }
+        None => false,
+    };
+    if state.samples() >= samples_per_pixel || converged(&state) {
+        return state;
     }
-    return Pixel::new(pixel_color, i, height - j);
-}
 
-fn prepare_jobs(height: u32, width: u32) -> Vec<(u32, u32)> {
-    let mut vec: Vec<(u32, u32)> = vec![];
-    for j in 0..height {
-        for i in 0..width {
-            vec.push((i, j));
+    // Samples are addressed by pixel and index, continued from the number of
+    // samples the pixel already has, so the image only depends on the seed
+    // and not on how the work was scheduled across threads, passes or
+    // resumes.
+    let mut path = PathSampler::new(sampler, settings.seed(), (i, j));
+    let target = state
+        .samples()
+        .saturating_add(PASS_SAMPLES)
+        .min(samples_per_pixel);
+    while state.samples() < target {
+        path.start_sample(state.samples());
+        let (dx, dy) = path.get_2d();
+        let u = (i as f64 + dx) / (width - 1) as f64;
+        let v = (j as f64 + dy) / (height - 1) as f64;
+        let r = cam.get_ray(u, v, &mut path);
+        let mut first_hit = HitRecord::new();
+        let color = integrator.radiance(
+            &r,
+            world,
+            lights,
+            background,
+            &mut path,
+            light_splats,
+            &mut first_hit,
+        );
+        state.color += color;
+        state.stats.add(luminance(color));
+        // `j` counts rows upwards, image space downwards.
+        splats.add(i as f64 + dx, (height - j) as f64 - dy, color);
+        if let Some(material_ids) = material_ids {
+            state.aov.samples += 1;
+            if first_hit.t() > 0.0 {
+                state
+                    .aov
+                    .accumulate(&AovSample::from_hit(&r, &first_hit, material_ids));
+            }
+        }
+
+        if state.samples().is_multiple_of(ADAPTIVE_BATCH) && converged(&state) {
+            break;
         }
     }
-    return vec;
+    return state;
 }
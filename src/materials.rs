@@ -0,0 +1,215 @@
+use std::{f64::consts::PI, fmt::Debug};
+
+use super::{
+    hittable::HitRecord,
+    math::calculate_reflectance,
+    onb::Onb,
+    ray::Ray,
+    sampler::PathSampler,
+    sampling::{cosine_hemisphere, cosine_hemisphere_pdf, uniform_sphere},
+    vec::Vector3,
+};
+
+// How surfaces scatter and emit light. Crates using shimmer as a library can
+// implement it for their own BSDFs and hand them to any shape. A material
+// that overrides `pdf` must override `bsdf` too: the default BSDF is zero,
+// and bidirectional path tracing would silently drop every connection
+// through the material.
+pub trait Material: Debug + Send + Sync {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vector3,
+        scattered: &mut Ray,
+        sampler: &mut PathSampler,
+    ) -> bool;
+
+    fn emitted(&self, _u: f64, _v: f64, _p: Vector3) -> Vector3 {
+        return Vector3::zero();
+    }
+
+    // Solid angle density with which `scatter` picks `scattered`. Zero for
+    // materials that only scatter into a discrete set of directions. A
+    // non-zero pdf means `scatter` samples its lobe exactly, so the BSDF
+    // times cosine is `attenuation * pdf` and lights may be sampled directly.
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        return 0.0;
+    }
+
+    // BSDF for light leaving along `scattered` that arrived along `r_in`,
+    // without the cosine. Zero wherever `pdf` is, so only materials that
+    // can be sampled directly can be connected to other path vertices.
+    fn bsdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Vector3 {
+        return Vector3::zero();
+    }
+
+    // Reflectance used by the albedo pass, white for materials without a
+    // meaningful surface color.
+    fn albedo(&self, _u: f64, _v: f64, _p: Vector3) -> Vector3 {
+        return Vector3::new(1.0, 1.0, 1.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lambertian {
+    pub albedo: Vector3,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Vector3) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vector3,
+        scattered: &mut Ray,
+        sampler: &mut PathSampler,
+    ) -> bool {
+        let (local, _) = cosine_hemisphere(sampler.get_2d());
+        let scatter_direction = Onb::build_from_w(rec.normal).local(local);
+
+        scattered.inherit(Ray::new(rec.p, scatter_direction));
+        attenuation.inherit(self.albedo);
+        return true;
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        return cosine_hemisphere_pdf(Vector3::dot(rec.normal, scattered.direction.unit()));
+    }
+
+    fn bsdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Vector3 {
+        if Vector3::dot(rec.normal, scattered.direction) <= 0.0 {
+            return Vector3::zero();
+        }
+        return self.albedo / PI;
+    }
+
+    fn albedo(&self, _u: f64, _v: f64, _p: Vector3) -> Vector3 {
+        return self.albedo;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metal {
+    pub albedo: Vector3,
+    pub fuzz: f64,
+}
+
+impl Metal {
+    pub fn new(albedo: Vector3, fuzz: f64) -> Self {
+        Self { albedo, fuzz }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vector3,
+        scattered: &mut Ray,
+        sampler: &mut PathSampler,
+    ) -> bool {
+        let reflected = Vector3::reflect(r_in.direction.unit(), rec.normal);
+        // Uniform point in the unit ball.
+        let (direction, _) = uniform_sphere(sampler.get_2d());
+        let fuzz = direction * sampler.get_1d().cbrt() * self.fuzz;
+        scattered.inherit(Ray::new(rec.p, reflected + fuzz));
+        attenuation.inherit(self.albedo);
+        return Vector3::dot(scattered.direction, rec.normal) > 0.0;
+    }
+
+    fn albedo(&self, _u: f64, _v: f64, _p: Vector3) -> Vector3 {
+        return self.albedo;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Dielectric {
+    pub ir: f64,
+}
+
+impl Dielectric {
+    pub fn new(index_of_refraction: f64) -> Self {
+        Self {
+            ir: index_of_refraction,
+        }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Vector3,
+        scattered: &mut Ray,
+        sampler: &mut PathSampler,
+    ) -> bool {
+        attenuation.inherit(Vector3::new(1.0, 1.0, 1.0));
+        let refraction_ratio = if rec.front_face {
+            1.0 / self.ir
+        } else {
+            self.ir
+        };
+        let unit_direction = r_in.direction.unit();
+
+        let cos_theta = Vector3::dot(-unit_direction, rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let cannot_refract = (refraction_ratio * sin_theta) > 1.0;
+
+        let direction = if cannot_refract
+            || calculate_reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
+        {
+            Vector3::reflect(unit_direction, rec.normal)
+        } else {
+            Vector3::refract(unit_direction, rec.normal, refraction_ratio)
+        };
+
+        scattered.inherit(Ray::new(rec.p, direction));
+        return true;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DiffuseLight {
+    pub emit: Vector3,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vector3) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _attenuation: &mut Vector3,
+        _scattered: &mut Ray,
+        _sampler: &mut PathSampler,
+    ) -> bool {
+        return false;
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, _p: Vector3) -> Vector3 {
+        return self.emit;
+    }
+
+    fn albedo(&self, _u: f64, _v: f64, _p: Vector3) -> Vector3 {
+        let max = self.emit.x.max(self.emit.y).max(self.emit.z);
+        if max <= 0.0 {
+            return Vector3::zero();
+        }
+        return self.emit / max;
+    }
+}
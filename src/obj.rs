@@ -0,0 +1,570 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+    sync::Arc,
+};
+
+use super::{
+    hittable::mesh::{MeshData, TriangleMesh},
+    materials::{Dielectric, Lambertian, Material, Metal},
+    vec::Vector3,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// The subset of an MTL entry that maps onto our materials.
+#[derive(Debug, Clone, Copy)]
+pub struct MtlEntry {
+    pub kd: Vector3,
+    pub ks: Vector3,
+    pub ns: f64,
+    pub ni: f64,
+    pub d: f64,
+    pub illum: u32,
+}
+
+impl Default for MtlEntry {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl MtlEntry {
+    pub fn new() -> Self {
+        Self {
+            kd: Vector3::new(0.8, 0.8, 0.8),
+            ks: Vector3::zero(),
+            ns: 0.0,
+            ni: 1.0,
+            d: 1.0,
+            illum: 1,
+        }
+    }
+
+    pub fn material(&self) -> Arc<dyn Material> {
+        // illum 4, 6, 7 and 9 are the transparent/refractive models.
+        let transparent = self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        if transparent {
+            let ir = if self.ni > 1.0 { self.ni } else { 1.5 };
+            return Arc::new(Dielectric::new(ir));
+        }
+
+        let specular = self.ks.x.max(self.ks.y).max(self.ks.z);
+        let diffuse = self.kd.x.max(self.kd.y).max(self.kd.z);
+        let reflective = self.illum == 3 || self.illum == 5 || self.illum == 8;
+        if specular > 0.0 && (reflective || (self.illum >= 2 && specular > diffuse)) {
+            // Map the Phong exponent to a fuzz radius, sharper highlights
+            // giving smoother metals.
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt().min(1.0);
+            return Arc::new(Metal::new(self.ks, fuzz));
+        }
+        return Arc::new(Lambertian::new(self.kd));
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    return fs::read_to_string(path).map_err(|err| ObjError::Io(path.to_path_buf(), err));
+}
+
+fn parse_error(path: &Path, line: usize, message: String) -> ObjError {
+    return ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+}
+
+fn parse_floats<const N: usize>(
+    tokens: SplitWhitespace,
+    required: usize,
+    defaults: [f64; N],
+    path: &Path,
+    line: usize,
+) -> Result<[f64; N], ObjError> {
+    let mut values = defaults;
+    let mut count = 0;
+    for token in tokens {
+        if count == N {
+            break;
+        }
+        values[count] = token
+            .parse()
+            .map_err(|_| parse_error(path, line, format!("invalid number '{}'", token)))?;
+        count += 1;
+    }
+    if count < required {
+        return Err(parse_error(
+            path,
+            line,
+            format!("expected at least {} numbers, found {}", required, count),
+        ));
+    }
+    return Ok(values);
+}
+
+fn parse_vector(tokens: SplitWhitespace, path: &Path, line: usize) -> Result<Vector3, ObjError> {
+    let [x, y, z] = parse_floats(tokens, 3, [0.0; 3], path, line)?;
+    return Ok(Vector3::new(x, y, z));
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, MtlEntry>, ObjError> {
+    return parse_mtl(&read_file(path)?, path);
+}
+
+// `path` is only used to report errors.
+fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, MtlEntry>, ObjError> {
+    let mut entries = HashMap::new();
+    let mut current: Option<(String, MtlEntry)> = None;
+
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let content = raw.split('#').next().unwrap_or("");
+        let mut tokens = content.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            if name.is_empty() {
+                return Err(parse_error(path, line, "newmtl without a name".to_string()));
+            }
+            if let Some((name, entry)) = current.take() {
+                entries.insert(name, entry);
+            }
+            current = Some((name, MtlEntry::new()));
+            continue;
+        }
+
+        let entry = match current.as_mut() {
+            Some((_, entry)) => entry,
+            None => {
+                return Err(parse_error(
+                    path,
+                    line,
+                    format!("'{}' before any newmtl", keyword),
+                ))
+            }
+        };
+
+        match keyword {
+            "Kd" => entry.kd = parse_vector(tokens, path, line)?,
+            "Ks" => entry.ks = parse_vector(tokens, path, line)?,
+            "Ns" => entry.ns = parse_floats(tokens, 1, [0.0], path, line)?[0],
+            "Ni" => entry.ni = parse_floats(tokens, 1, [1.0], path, line)?[0],
+            "d" => entry.d = parse_floats(tokens, 1, [1.0], path, line)?[0],
+            "Tr" => entry.d = 1.0 - parse_floats(tokens, 1, [0.0], path, line)?[0],
+            "illum" => {
+                let value = tokens.next().unwrap_or("");
+                entry.illum = value
+                    .parse()
+                    .map_err(|_| parse_error(path, line, format!("invalid illum '{}'", value)))?;
+            }
+            // Textures, ambient and emission terms are not supported yet.
+            _ => {}
+        }
+    }
+
+    if let Some((name, entry)) = current {
+        entries.insert(name, entry);
+    }
+    return Ok(entries);
+}
+
+// Accumulates the triangles of one group/material pair, de-duplicating the
+// (position, texcoord, normal) index triples into shared vertices.
+struct MeshBuilder {
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[u32; 3]>,
+    has_normals: bool,
+    has_uvs: bool,
+}
+
+impl MeshBuilder {
+    fn new() -> Self {
+        Self {
+            vertex_map: HashMap::new(),
+            positions: vec![],
+            normals: vec![],
+            uvs: vec![],
+            indices: vec![],
+            has_normals: false,
+            has_uvs: false,
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        key: (usize, Option<usize>, Option<usize>),
+        positions: &[Vector3],
+        uvs: &[(f64, f64)],
+        normals: &[Vector3],
+    ) -> u32 {
+        if let Some(index) = self.vertex_map.get(&key) {
+            return *index;
+        }
+        let index = self.positions.len() as u32;
+        self.positions.push(positions[key.0]);
+        // Vertices without a normal get a zero normal. Faces only use them
+        // with each other, and the triangle intersection replaces the zero
+        // interpolated normal with the geometric one.
+        self.normals
+            .push(key.2.map(|n| normals[n]).unwrap_or_else(Vector3::zero));
+        self.uvs.push(key.1.map(|t| uvs[t]).unwrap_or((0.0, 0.0)));
+        self.has_normals |= key.2.is_some();
+        self.has_uvs |= key.1.is_some();
+        self.vertex_map.insert(key, index);
+        return index;
+    }
+
+    fn build(mut self, mat: Arc<dyn Material>) -> MeshData {
+        if !self.has_normals {
+            self.normals.clear();
+        }
+        if !self.has_uvs {
+            self.uvs.clear();
+        }
+        return MeshData {
+            positions: self.positions,
+            normals: self.normals,
+            uvs: self.uvs,
+            indices: self.indices,
+            mat,
+        };
+    }
+}
+
+// Resolves a 1-based (or negative, relative to the end) OBJ index.
+fn resolve_index(token: &str, count: usize, path: &Path, line: usize) -> Result<usize, ObjError> {
+    let value: i64 = token
+        .parse()
+        .map_err(|_| parse_error(path, line, format!("invalid index '{}'", token)))?;
+    let resolved = if value > 0 {
+        value - 1
+    } else {
+        count as i64 + value
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(parse_error(
+            path,
+            line,
+            format!("index {} out of range ({} available)", value, count),
+        ));
+    }
+    return Ok(resolved as usize);
+}
+
+pub fn load_obj(
+    path: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let meshes = parse_obj(&read_file(path)?, path, default_material)?;
+    return Ok(meshes.into_iter().map(TriangleMesh::new).collect());
+}
+
+// Material libraries are looked up next to `path`.
+fn parse_obj(
+    source: &str,
+    path: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<Vec<MeshData>, ObjError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions: Vec<Vector3> = vec![];
+    let mut normals: Vec<Vector3> = vec![];
+    let mut uvs: Vec<(f64, f64)> = vec![];
+    let mut materials: HashMap<String, MtlEntry> = HashMap::new();
+
+    let mut group = String::new();
+    let mut material_name: Option<String> = None;
+    let mut builders: Vec<((String, Option<String>), MeshBuilder)> = vec![];
+
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let content = raw.split('#').next().unwrap_or("");
+        let mut tokens = content.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => positions.push(parse_vector(tokens, path, line)?),
+            "vn" => normals.push(parse_vector(tokens, path, line)?),
+            "vt" => {
+                let [u, v] = parse_floats(tokens, 1, [0.0; 2], path, line)?;
+                uvs.push((u, v));
+            }
+            "g" | "o" => group = tokens.collect::<Vec<_>>().join(" "),
+            "usemtl" => material_name = Some(tokens.collect::<Vec<_>>().join(" ")),
+            "mtllib" => {
+                for file in tokens {
+                    materials.extend(load_mtl(&directory.join(file))?);
+                }
+            }
+            "f" => {
+                let mut corners = vec![];
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let p = resolve_index(parts.next().unwrap_or(""), positions.len(), path, line)?;
+                    let t = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(resolve_index(t, uvs.len(), path, line)?),
+                        _ => None,
+                    };
+                    let n = match parts.next() {
+                        Some(n) if !n.is_empty() => {
+                            Some(resolve_index(n, normals.len(), path, line)?)
+                        }
+                        _ => None,
+                    };
+                    corners.push((p, t, n));
+                }
+                if corners.len() < 3 {
+                    return Err(parse_error(
+                        path,
+                        line,
+                        format!("face with {} vertices", corners.len()),
+                    ));
+                }
+                // Shading normals are only interpolated across faces that
+                // give one for every corner, the rest use the geometric one.
+                if corners.iter().any(|(_, _, n)| n.is_none()) {
+                    for corner in &mut corners {
+                        corner.2 = None;
+                    }
+                }
+
+                let key = (group.clone(), material_name.clone());
+                let builder = match builders.iter().position(|(k, _)| *k == key) {
+                    Some(i) => &mut builders[i].1,
+                    None => {
+                        builders.push((key, MeshBuilder::new()));
+                        &mut builders.last_mut().unwrap().1
+                    }
+                };
+
+                let vertices: Vec<u32> = corners
+                    .into_iter()
+                    .map(|corner| builder.vertex(corner, &positions, &uvs, &normals))
+                    .collect();
+                // Triangulate polygons as a fan around the first vertex.
+                for i in 1..vertices.len() - 1 {
+                    builder
+                        .indices
+                        .push([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are ignored.
+            _ => {}
+        }
+    }
+
+    let mut meshes = vec![];
+    for ((_, name), builder) in builders {
+        let mat = match name.as_ref().and_then(|name| materials.get(name)) {
+            Some(entry) => entry.material(),
+            None => Arc::clone(&default_material),
+        };
+        meshes.push(builder.build(mat));
+    }
+    return Ok(meshes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+";
+
+    fn gray() -> Arc<dyn Material> {
+        return Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    }
+
+    fn parse(source: &str) -> Result<Vec<MeshData>, ObjError> {
+        return parse_obj(source, Path::new("test.obj"), gray());
+    }
+
+    fn kind(mat: &Arc<dyn Material>) -> String {
+        let debug = format!("{:?}", mat);
+        return debug.split(' ').next().unwrap_or("").to_string();
+    }
+
+    fn components(vectors: &[Vector3]) -> Vec<[f64; 3]> {
+        return vectors.iter().map(|v| [v.x, v.y, v.z]).collect();
+    }
+
+    fn error_line(result: Result<Vec<MeshData>, ObjError>) -> usize {
+        return match result {
+            Err(ObjError::Parse { line, .. }) => line,
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("parsed without error"),
+        };
+    }
+
+    #[test]
+    fn polygons_are_triangulated_as_a_fan() {
+        let meshes = parse(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].positions.len(), 4);
+        assert_eq!(meshes[0].indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(meshes[0].normals.is_empty() && meshes[0].uvs.is_empty());
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let meshes = parse(&format!("{}f -4 -3 -2 -1\n", SQUARE)).unwrap();
+        assert_eq!(meshes[0].indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(components(&meshes[0].positions[3..]), vec![[0.0, 1.0, 0.0]]);
+    }
+
+    #[test]
+    fn shared_corners_become_shared_vertices() {
+        let meshes = parse(&format!("{}f 1 2 3\nf 1 3 4\n", SQUARE)).unwrap();
+        assert_eq!(meshes[0].positions.len(), 4);
+        assert_eq!(meshes[0].indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn normal_and_texcoord_forms() {
+        let meshes = parse(&format!("{}f 1//1 2//1 3//1\n", SQUARE)).unwrap();
+        assert_eq!(components(&meshes[0].normals), vec![[0.0, 0.0, 1.0]; 3]);
+        assert!(meshes[0].uvs.is_empty());
+
+        let meshes = parse(&format!("{}f 1/1 2/2 3/3\n", SQUARE)).unwrap();
+        assert!(meshes[0].normals.is_empty());
+        assert_eq!(meshes[0].uvs, vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+
+        let meshes = parse(&format!("{}f 1/1/1 2/2/1 3/3/1\n", SQUARE)).unwrap();
+        assert_eq!(meshes[0].normals.len(), 3);
+        assert_eq!(meshes[0].uvs.len(), 3);
+    }
+
+    #[test]
+    fn faces_missing_a_normal_drop_all_of_them() {
+        let source = format!("{}f 1//1 2//1 3\nf 1//1 3//1 4//1\n", SQUARE);
+        let meshes = parse(&source).unwrap();
+        let mesh = &meshes[0];
+        // The first face uses position-only corners, the second its own.
+        assert_eq!(mesh.positions.len(), 6);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(components(&mesh.normals[..3]), vec![[0.0; 3]; 3]);
+        assert_eq!(components(&mesh.normals[3..]), vec![[0.0, 0.0, 1.0]; 3]);
+    }
+
+    #[test]
+    fn groups_and_materials_split_meshes() {
+        let source = format!(
+            "{}g a\nf 1 2 3\ng b\nf 1 3 4\ng a\nf 2 3 4\nusemtl missing\nf 1 2 4\n",
+            SQUARE
+        );
+        let meshes = parse(&source).unwrap();
+        let triangles: Vec<usize> = meshes.iter().map(|mesh| mesh.indices.len()).collect();
+        assert_eq!(triangles, vec![2, 1, 1]);
+        // An unknown material falls back to the default one.
+        assert!(meshes.iter().all(|mesh| kind(&mesh.mat) == "Lambertian"));
+    }
+
+    #[test]
+    fn usemtl_picks_the_library_material() {
+        let directory = std::env::temp_dir().join(format!("shimmer-obj-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("test.mtl"),
+            "newmtl glass\nd 0.5\nnewmtl chrome\nKs 0.9 0.9 0.9\nillum 3\n",
+        )
+        .unwrap();
+        let source = format!(
+            "mtllib test.mtl\n{}usemtl glass\nf 1 2 3\nusemtl chrome\nf 1 3 4\n",
+            SQUARE
+        );
+        let meshes = parse_obj(&source, &directory.join("test.obj"), gray());
+        fs::remove_dir_all(&directory).unwrap();
+        let kinds: Vec<String> = meshes.unwrap().iter().map(|mesh| kind(&mesh.mat)).collect();
+        assert_eq!(kinds, vec!["Dielectric", "Metal"]);
+    }
+
+    #[test]
+    fn mtl_entries_map_onto_materials() {
+        let source = "
+newmtl diffuse
+Kd 0.7 0.2 0.2
+
+newmtl opaque
+Tr 0
+illum 2
+
+newmtl window
+Tr 0.25
+Ni 1.33
+
+newmtl glass
+illum 7
+
+newmtl brushed # a comment
+Kd 0.1 0.1 0.1
+Ks 0.8 0.8 0.8
+Ns 100
+illum 2
+";
+        let entries = parse_mtl(source, Path::new("test.mtl")).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries["opaque"].d, 1.0);
+        assert_eq!(entries["window"].d, 0.75);
+        assert_eq!(entries["window"].ni, 1.33);
+        let kind_of = |name: &str| kind(&entries[name].material());
+        assert_eq!(kind_of("diffuse"), "Lambertian");
+        assert_eq!(kind_of("opaque"), "Lambertian");
+        assert_eq!(kind_of("window"), "Dielectric");
+        assert_eq!(kind_of("glass"), "Dielectric");
+        assert_eq!(kind_of("brushed"), "Metal");
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        assert_eq!(error_line(parse(&format!("{}f 1 2 5\n", SQUARE))), 11);
+        assert_eq!(error_line(parse(&format!("{}f 1 0 2\n", SQUARE))), 11);
+        assert_eq!(error_line(parse(&format!("{}f 1 2\n", SQUARE))), 11);
+        assert_eq!(error_line(parse("v 1 x 0\n")), 1);
+        let mtl = parse_mtl("Kd 1 1 1\n", Path::new("test.mtl"));
+        assert!(matches!(mtl, Err(ObjError::Parse { line: 1, .. })));
+    }
+}
@@ -0,0 +1,27 @@
+use super::vec::Vector3;
+
+// Orthonormal basis with `w` along a given direction.
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vector3,
+    pub v: Vector3,
+    pub w: Vector3,
+}
+
+impl Onb {
+    pub fn build_from_w(n: Vector3) -> Self {
+        let w = n.unit();
+        let a = if w.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vector3::cross(w, a).unit();
+        let u = Vector3::cross(w, v);
+        return Self { u, v, w };
+    }
+
+    pub fn local(&self, a: Vector3) -> Vector3 {
+        return self.u * a.x + self.v * a.y + self.w * a.z;
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use exr::prelude::{
+    f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec,
+    WritableImage,
+};
+use serde::Deserialize;
+
+use super::{math::clamp, vec::Vector3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        return match extension.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("exr") => Ok(ImageFormat::Exr),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            _ => Err(format!(
+                "cannot tell the image format of `{}`, expected a .png, .exr, .hdr or .pfm file",
+                path.display()
+            )),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExrPrecision {
+    #[default]
+    Half,
+    Float,
+}
+
+pub fn save_image(path: &Path, width: u32, height: u32, data: &[u8]) -> io::Result<()> {
+    let file = File::create(path)?;
+    let buf_writer = &mut BufWriter::new(file);
+    let mut encoder = png::Encoder::new(buf_writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    return Ok(());
+}
+
+// Channels of one pass of an OpenEXR file, with the names of the color
+// components or of the single scalar channel.
+pub enum ExrChannels<'a> {
+    Color(&'a [Vector3], [&'static str; 3]),
+    Scalar(&'a [f64], &'static str),
+    Id(&'a [u32]),
+}
+
+// Writes all passes into a single part file. The first pass should be
+// unnamed, the channels of further passes are prefixed with their name
+// (`albedo.R`, `depth.Z`, ...) the way compositing packages expect. Ids are
+// stored as unsigned integers, everything else with `precision`.
+pub fn save_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    passes: &[(&str, ExrChannels)],
+    precision: ExrPrecision,
+) -> io::Result<()> {
+    let float_samples = |values: Vec<f64>| match precision {
+        ExrPrecision::Half => FlatSamples::F16(values.into_iter().map(f16::from_f64).collect()),
+        ExrPrecision::Float => FlatSamples::F32(values.into_iter().map(|x| x as f32).collect()),
+    };
+    let channel_name = |pass: &str, component: &str| {
+        if pass.is_empty() {
+            return component.to_string();
+        }
+        if component.is_empty() {
+            return pass.to_string();
+        }
+        return format!("{}.{}", pass, component);
+    };
+
+    let mut channels = SmallVec::new();
+    for (name, pass) in passes {
+        match pass {
+            ExrChannels::Color(pixels, components) => {
+                for (axis, component) in components.iter().enumerate() {
+                    let values = pixels.iter().map(|color| color[axis]).collect();
+                    let name = channel_name(name, component);
+                    channels.push(AnyChannel::new(name.as_str(), float_samples(values)));
+                }
+            }
+            ExrChannels::Scalar(values, component) => {
+                let name = channel_name(name, component);
+                channels.push(AnyChannel::new(
+                    name.as_str(),
+                    float_samples(values.to_vec()),
+                ));
+            }
+            ExrChannels::Id(ids) => {
+                let name = channel_name(name, "");
+                channels.push(AnyChannel::new(
+                    name.as_str(),
+                    FlatSamples::U32(ids.to_vec()),
+                ));
+            }
+        }
+    }
+
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    return Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(|err| io::Error::other(err.to_string()));
+}
+
+// Radiance RGBE file with flat, uncompressed scanlines.
+pub fn save_hdr(path: &Path, width: u32, height: u32, pixels: &[Vector3]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+    for color in pixels {
+        writer.write_all(&rgbe(*color))?;
+    }
+    return writer.flush();
+}
+
+fn rgbe(color: Vector3) -> [u8; 4] {
+    let channels = [color.x, color.y, color.z].map(|x| if x.is_nan() { 0.0 } else { x.max(0.0) });
+    let max = channels[0].max(channels[1]).max(channels[2]);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // Shared exponent such that max = m * 2^exponent with m in [0.5, 1).
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(exponent);
+    if exponent + 128 > 255 {
+        return [255, 255, 255, 255];
+    }
+    if exponent + 128 < 0 {
+        return [0, 0, 0, 0];
+    }
+    return [
+        (channels[0] * scale).min(255.0) as u8,
+        (channels[1] * scale).min(255.0) as u8,
+        (channels[2] * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ];
+}
+
+// Portable float map, little endian, with rows stored bottom to top.
+pub fn save_pfm(path: &Path, width: u32, height: u32, pixels: &[Vector3]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+    for row in pixels.chunks_exact(width as usize).rev() {
+        for color in row {
+            for x in [color.x, color.y, color.z] {
+                writer.write_all(&(x as f32).to_le_bytes())?;
+            }
+        }
+    }
+    return writer.flush();
+}
+
+// Transfer function from linear radiance to the encoded values written to
+// 8-bit images.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ColorTransform {
+    Linear,
+    #[default]
+    Srgb,
+    Gamma(f64),
+}
+
+impl ColorTransform {
+    pub fn encode(&self, x: f64) -> f64 {
+        // NaN fails both comparisons in `clamp`, map it to black instead.
+        let x = if x.is_nan() { 0.0 } else { clamp(x, 0.0, 1.0) };
+        return match self {
+            ColorTransform::Linear => x,
+            ColorTransform::Srgb => {
+                if x <= 0.0031308 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            ColorTransform::Gamma(gamma) => x.powf(1.0 / gamma),
+        };
+    }
+}
+
+// Accepts `linear`, `srgb`, `gamma` for a 2.2 power curve and `gamma:<value>`.
+impl FromStr for ColorTransform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        return match (name, value) {
+            ("linear", None) => Ok(ColorTransform::Linear),
+            ("srgb", None) => Ok(ColorTransform::Srgb),
+            ("gamma", None) => Ok(ColorTransform::Gamma(2.2)),
+            ("gamma", Some(value)) => match value.parse::<f64>() {
+                Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Ok(ColorTransform::Gamma(gamma)),
+                _ => Err(format!("invalid gamma `{}`", value)),
+            },
+            _ => Err(format!(
+                "unknown color transform `{}`, expected linear, srgb, gamma or gamma:<value>",
+                s
+            )),
+        };
+    }
+}
+
+impl TryFrom<String> for ColorTransform {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return s.parse();
+    }
+}
+
+pub fn push_rgb(r: f64, g: f64, b: f64, data: &mut Vec<u8>, transform: ColorTransform) {
+    for x in [r, g, b] {
+        data.push((transform.encode(x) * 255.0).round() as u8);
+    }
+}
+
+pub fn push_vector(color: Vector3, data: &mut Vec<u8>, transform: ColorTransform) {
+    push_rgb(color.x, color.y, color.z, data, transform)
+}
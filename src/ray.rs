@@ -0,0 +1,45 @@
+use super::vec::Vector3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vector3,
+    pub direction: Vector3,
+}
+
+impl Ray {
+    pub fn inherit(&mut self, new: Self) {
+        self.origin = new.origin;
+        self.direction = new.direction;
+    }
+    pub fn zero() -> Self {
+        Self {
+            origin: Vector3::zero(),
+            direction: Vector3::zero(),
+        }
+    }
+    pub fn new(origin: Vector3, direction: Vector3) -> Self {
+        Self { origin, direction }
+    }
+    pub fn at(&self, t: f64) -> Vector3 {
+        return self.origin + self.direction * t;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Background {
+    Sky,
+    Solid(Vector3),
+}
+
+impl Background {
+    pub fn color(&self, r: &Ray) -> Vector3 {
+        match self {
+            Background::Sky => {
+                let unit_direction = r.direction.unit();
+                let t = (unit_direction.y + 1.0) * 0.5;
+                return Vector3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vector3::new(0.5, 0.7, 1.0) * t;
+            }
+            Background::Solid(color) => return *color,
+        }
+    }
+}
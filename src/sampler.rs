@@ -0,0 +1,548 @@
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use super::math::mix_seed;
+
+// Dimensions reserved for the pixel position and the lens.
+const CAMERA_DIMENSIONS: u32 = 4;
+// Dimensions reserved for every path vertex, split between the decisions
+// taken there.
+const VERTEX_DIMENSIONS: u32 = 10;
+
+// The two subpaths of bidirectional samples, see `PathSampler::start_subpath`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subpath {
+    Camera,
+    Light,
+}
+
+// Decisions taken at a path vertex. Each draws from its own dimensions
+// within the vertex, whether or not the ones before it were taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    // The scattered direction, and reflection or refraction for glass.
+    Scatter,
+    // A light and a point on it, for light sampling or to start a light
+    // subpath.
+    Light,
+    // Whether Russian roulette ends the path.
+    Roulette,
+    // A point on the lens, to connect the vertex to the camera.
+    Lens,
+}
+
+impl Decision {
+    // First dimension within the vertex and number of dimensions.
+    fn dimensions(&self) -> (u32, u32) {
+        return match self {
+            Decision::Scatter => (0, 4),
+            Decision::Light => (4, 3),
+            Decision::Roulette => (7, 1),
+            Decision::Lens => (8, 2),
+        };
+    }
+}
+
+// Sample patterns for the random decisions along sample paths. Every
+// pattern maps a pixel, the index of a sample within it and a dimension to
+// a value in [0, 1), and always returns the same value for the same inputs.
+pub trait Sampler: Send + Sync {
+    fn sample_1d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f64;
+    fn sample_2d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum SamplerKind {
+    // Independent uniform random numbers.
+    Random,
+    // Jittered strata, shuffled per pixel and dimension.
+    Stratified,
+    // Halton sequence with a random shift per pixel and dimension.
+    Halton,
+    // Owen-scrambled Sobol points, padded per pair of dimensions.
+    #[default]
+    Sobol,
+    // Sobol points shared by all pixels, shifted by a blue noise texture so
+    // the remaining error is spread as high frequency noise.
+    BlueNoise,
+}
+
+impl SamplerKind {
+    // `samples_per_pixel` sets the number of strata of the stratified
+    // sampler, the other samplers do not depend on it.
+    pub fn build(&self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        return match self {
+            SamplerKind::Random => Box::new(RandomSampler { seed }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {
+                seed,
+                strata: samples_per_pixel.clamp(1, 1 << 16),
+            }),
+            SamplerKind::Halton => Box::new(HaltonSampler { seed }),
+            SamplerKind::Sobol => Box::new(SobolSampler { seed }),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler {
+                seed,
+                texture: blue_noise_texture(),
+            }),
+        };
+    }
+}
+
+// Hands out the dimensions of one sample path. The camera and every
+// decision at every vertex along the path start at fixed dimensions, so the
+// same decision at the same depth always draws from the same dimension, no
+// matter how many values earlier decisions used. Values beyond a decision's
+// share come from independent random numbers.
+pub struct PathSampler<'a> {
+    sampler: &'a dyn Sampler,
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+    end: u32,
+    // First dimension of the current vertex.
+    base: u32,
+    subpath: Subpath,
+    bidirectional: bool,
+    // Vertices started so far along the camera and the light subpath.
+    vertices: [u32; 2],
+    extra: u64,
+}
+
+impl<'a> PathSampler<'a> {
+    pub fn new(sampler: &'a dyn Sampler, seed: u64, pixel: (u32, u32)) -> Self {
+        Self {
+            sampler,
+            seed: mix_seed(seed, pixel_key(pixel)),
+            pixel,
+            index: 0,
+            dimension: 0,
+            end: CAMERA_DIMENSIONS,
+            base: 0,
+            subpath: Subpath::Camera,
+            bidirectional: false,
+            vertices: [0, 0],
+            extra: 0,
+        }
+    }
+
+    // Starts sample `index` of the pixel with the camera dimensions.
+    pub fn start_sample(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+        self.end = CAMERA_DIMENSIONS;
+        self.base = 0;
+        self.subpath = Subpath::Camera;
+        self.bidirectional = false;
+        self.vertices = [0, 0];
+        self.extra = 0;
+    }
+
+    // Makes the following vertices continue `subpath`. Once a sample traces
+    // a light subpath, the vertices of both subpaths take turns along the
+    // dimensions, so each vertex keeps its dimensions however long the
+    // other subpath is.
+    pub fn start_subpath(&mut self, subpath: Subpath) {
+        self.subpath = subpath;
+        self.bidirectional = true;
+    }
+
+    // Moves on to the dimensions of the next vertex along the subpath,
+    // starting with the scattering decision.
+    pub fn start_vertex(&mut self) {
+        let vertex = self.vertices[self.subpath as usize];
+        self.base = self.vertex_base(self.subpath, vertex);
+        self.vertices[self.subpath as usize] += 1;
+        self.start_decision(Decision::Scatter);
+    }
+
+    // Moves to the dimensions of `decision` at an earlier `vertex` of
+    // `subpath`, counted from zero, for connections made after the subpaths
+    // were traced.
+    pub fn start_connection(&mut self, subpath: Subpath, vertex: u32, decision: Decision) {
+        self.base = self.vertex_base(subpath, vertex);
+        self.start_decision(decision);
+    }
+
+    fn vertex_base(&self, subpath: Subpath, vertex: u32) -> u32 {
+        let slot = match (self.bidirectional, subpath) {
+            (false, _) => vertex,
+            (true, Subpath::Camera) => 2 * vertex,
+            (true, Subpath::Light) => 2 * vertex + 1,
+        };
+        return CAMERA_DIMENSIONS + slot * VERTEX_DIMENSIONS;
+    }
+
+    // Moves on to the dimensions of `decision` at the current vertex.
+    pub fn start_decision(&mut self, decision: Decision) {
+        let (offset, count) = decision.dimensions();
+        self.dimension = self.base + offset;
+        self.end = self.dimension + count;
+    }
+
+    pub fn get_1d(&mut self) -> f64 {
+        if self.dimension >= self.end {
+            return self.random();
+        }
+        let value = self
+            .sampler
+            .sample_1d(self.pixel, self.index, self.dimension);
+        self.dimension += 1;
+        return value;
+    }
+
+    pub fn get_2d(&mut self) -> (f64, f64) {
+        if self.dimension + 2 > self.end {
+            return (self.random(), self.random());
+        }
+        let value = self
+            .sampler
+            .sample_2d(self.pixel, self.index, self.dimension);
+        self.dimension += 2;
+        return value;
+    }
+
+    fn random(&mut self) -> f64 {
+        self.extra += 1;
+        let stream = mix_seed(self.seed, self.index as u64);
+        return unit_float(mix_seed(stream, u64::MAX - self.extra));
+    }
+}
+
+fn pixel_key(pixel: (u32, u32)) -> u64 {
+    return (pixel.1 as u64) << 32 | pixel.0 as u64;
+}
+
+// Maps the top 53 bits to [0, 1).
+fn unit_float(bits: u64) -> f64 {
+    return (bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64);
+}
+
+// Seed for one pixel and dimension of a sampler seeded with `seed`.
+fn dimension_seed(seed: u64, pixel: (u32, u32), dimension: u32) -> u64 {
+    return mix_seed(mix_seed(seed, pixel_key(pixel)), dimension as u64);
+}
+
+struct RandomSampler {
+    seed: u64,
+}
+
+impl Sampler for RandomSampler {
+    fn sample_1d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f64 {
+        let seed = dimension_seed(self.seed, pixel, dimension);
+        return unit_float(mix_seed(seed, index as u64));
+    }
+
+    fn sample_2d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> (f64, f64) {
+        return (
+            self.sample_1d(pixel, index, dimension),
+            self.sample_1d(pixel, index, dimension + 1),
+        );
+    }
+}
+
+// Each run of `strata` samples covers every stratum once, in an order
+// shuffled per pixel, dimension and run. Pairs of dimensions are stratified
+// jointly on the largest square grid that fits.
+struct StratifiedSampler {
+    seed: u64,
+    strata: u32,
+}
+
+impl StratifiedSampler {
+    fn stratum(&self, pixel: (u32, u32), index: u32, dimension: u32, strata: u32) -> (u32, u64) {
+        let run = index / strata;
+        let seed = mix_seed(dimension_seed(self.seed, pixel, dimension), run as u64);
+        return (
+            permutation_element(index % strata, strata, seed as u32),
+            seed,
+        );
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn sample_1d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f64 {
+        let (stratum, seed) = self.stratum(pixel, index, dimension, self.strata);
+        let jitter = unit_float(mix_seed(seed, index as u64));
+        return (stratum as f64 + jitter) / self.strata as f64;
+    }
+
+    fn sample_2d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> (f64, f64) {
+        let side = (self.strata as f64).sqrt() as u32;
+        let (stratum, seed) = self.stratum(pixel, index, dimension, side * side);
+        let jitter = mix_seed(seed, index as u64);
+        let (jx, jy) = (unit_float(jitter), unit_float(mix_seed(jitter, 1)));
+        return (
+            ((stratum % side) as f64 + jx) / side as f64,
+            ((stratum / side) as f64 + jy) / side as f64,
+        );
+    }
+}
+
+// Element `i` of a random permutation of 0..`length` chosen by `seed`,
+// Kensler's hash based permutation from "Correlated Multi-Jittered
+// Sampling".
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    return i.wrapping_add(seed) % length;
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Dimensions past the prime table fall back to random numbers.
+struct HaltonSampler {
+    seed: u64,
+}
+
+impl Sampler for HaltonSampler {
+    fn sample_1d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f64 {
+        let seed = dimension_seed(self.seed, pixel, dimension);
+        let Some(base) = PRIMES.get(dimension as usize) else {
+            return unit_float(mix_seed(seed, index as u64));
+        };
+        // Cranley-Patterson rotation, the same for every sample of a pixel.
+        let value = radical_inverse(*base, index) + unit_float(seed);
+        return if value >= 1.0 { value - 1.0 } else { value };
+    }
+
+    fn sample_2d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> (f64, f64) {
+        return (
+            self.sample_1d(pixel, index, dimension),
+            self.sample_1d(pixel, index, dimension + 1),
+        );
+    }
+}
+
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed = 0.0;
+    let mut scale = inverse_base;
+    while index > 0 {
+        reversed += (index % base) as f64 * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+    return reversed;
+}
+
+// Burley's "Practical Hash-based Owen Scrambling": every pair of dimensions
+// takes the first two Sobol dimensions, with the index shuffled and the
+// points scrambled by seeds of its own.
+struct SobolSampler {
+    seed: u64,
+}
+
+impl Sampler for SobolSampler {
+    fn sample_1d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f64 {
+        return self.sample_2d(pixel, index, dimension).0;
+    }
+
+    fn sample_2d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> (f64, f64) {
+        return owen_sobol_2d(dimension_seed(self.seed, pixel, dimension), index);
+    }
+}
+
+fn owen_sobol_2d(seed: u64, index: u32) -> (f64, f64) {
+    let shuffled = nested_uniform_scramble(index, seed as u32);
+    let x = nested_uniform_scramble(sobol(shuffled, 0), mix_seed(seed, 1) as u32);
+    let y = nested_uniform_scramble(sobol(shuffled, 1), mix_seed(seed, 2) as u32);
+    return (
+        x as f64 / (1u64 << 32) as f64,
+        y as f64 / (1u64 << 32) as f64,
+    );
+}
+
+// The first two dimensions of the Sobol sequence. The first is the van der
+// Corput sequence, the second has direction numbers v_k = v_(k-1) ^ (v_(k-1)
+// >> 1) from the polynomial x + 1.
+fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    let mut index = index;
+    while index > 0 {
+        if index & 1 == 1 {
+            result ^= direction;
+        }
+        direction ^= direction >> 1;
+        index >>= 1;
+    }
+    return result;
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return laine_karras_permutation(x.reverse_bits(), seed).reverse_bits();
+}
+
+// Hash that only lets bits affect more significant ones, an Owen scramble
+// of the bit reversed value.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    return x;
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+// Georgiev and Fajardo's "Blue-noise Dithered Sampling": all pixels use the
+// same scrambled Sobol points, each shifted by the blue noise texture at an
+// offset that differs per dimension.
+struct BlueNoiseSampler {
+    seed: u64,
+    texture: &'static [f64],
+}
+
+impl BlueNoiseSampler {
+    fn shift(&self, pixel: (u32, u32), dimension: u32) -> f64 {
+        let offset = mix_seed(self.seed, dimension as u64);
+        let x = (pixel.0 as usize + offset as usize) % BLUE_NOISE_SIZE;
+        let y = (pixel.1 as usize + (offset >> 32) as usize) % BLUE_NOISE_SIZE;
+        return self.texture[y * BLUE_NOISE_SIZE + x];
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn sample_1d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> f64 {
+        return self.sample_2d(pixel, index, dimension).0;
+    }
+
+    fn sample_2d(&self, pixel: (u32, u32), index: u32, dimension: u32) -> (f64, f64) {
+        let (x, y) = owen_sobol_2d(mix_seed(self.seed, dimension as u64), index);
+        let wrap = |value: f64| if value >= 1.0 { value - 1.0 } else { value };
+        return (
+            wrap(x + self.shift(pixel, dimension)),
+            wrap(y + self.shift(pixel, dimension + 1)),
+        );
+    }
+}
+
+fn blue_noise_texture() -> &'static [f64] {
+    static TEXTURE: OnceLock<Vec<f64>> = OnceLock::new();
+    return TEXTURE.get_or_init(void_and_cluster);
+}
+
+// Ulichney's void-and-cluster method on a tiling texture. Pixels are ranked
+// by the order in which they fill the largest voids of a gaussian energy
+// field, and the ranks become values in (0, 1).
+fn void_and_cluster() -> Vec<f64> {
+    const SIZE: usize = BLUE_NOISE_SIZE;
+    const COUNT: usize = SIZE * SIZE;
+    const SIGMA: f64 = 1.5;
+
+    let mut kernel = vec![0.0; COUNT];
+    for dy in 0..SIZE {
+        for dx in 0..SIZE {
+            let x = dx.min(SIZE - dx) as f64;
+            let y = dy.min(SIZE - dy) as f64;
+            kernel[dy * SIZE + dx] = (-(x * x + y * y) / (2.0 * SIGMA * SIGMA)).exp();
+        }
+    }
+    let splat = |energy: &mut [f64], p: usize, sign: f64| {
+        let (px, py) = (p % SIZE, p / SIZE);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let dx = (x + SIZE - px) % SIZE;
+                let dy = (y + SIZE - py) % SIZE;
+                energy[y * SIZE + x] += sign * kernel[dy * SIZE + dx];
+            }
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f64]| {
+        return (0..COUNT)
+            .filter(|p| pattern[*p])
+            .max_by(|a, b| energy[*a].total_cmp(&energy[*b]))
+            .unwrap();
+    };
+    let largest_void = |pattern: &[bool], energy: &[f64]| {
+        return (0..COUNT)
+            .filter(|p| !pattern[*p])
+            .min_by(|a, b| energy[*a].total_cmp(&energy[*b]))
+            .unwrap();
+    };
+
+    // Random initial points, then swap points from the tightest cluster into
+    // the largest void until that settles.
+    let mut pattern = vec![false; COUNT];
+    let mut energy = vec![0.0; COUNT];
+    let initial = COUNT / 10;
+    let mut placed = 0;
+    let mut stream = 0;
+    while placed < initial {
+        stream += 1;
+        let p = (mix_seed(0x5eed, stream) % COUNT as u64) as usize;
+        if !pattern[p] {
+            pattern[p] = true;
+            splat(&mut energy, p, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; COUNT];
+    let (initial_pattern, initial_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..initial).rev() {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+    let (mut pattern, mut energy) = (initial_pattern, initial_energy);
+    for rank in initial..COUNT {
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        splat(&mut energy, void, 1.0);
+        ranks[void] = rank;
+    }
+    return ranks
+        .into_iter()
+        .map(|rank| (rank as f64 + 0.5) / COUNT as f64)
+        .collect();
+}
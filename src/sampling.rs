@@ -0,0 +1,353 @@
+use std::f64::consts::PI;
+
+use super::vec::Vector3;
+
+// Closed-form warps from uniform samples in [0, 1)^2 to points and
+// directions. Each returns the sample together with its density. Directions
+// are in a local frame around +z unless stated otherwise, see `Onb::local`.
+
+// Shirley and Chiu's concentric map onto the unit disk in the xy plane,
+// with density per unit area.
+pub fn concentric_disk(u: (f64, f64)) -> (Vector3, f64) {
+    let (x, y) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return (Vector3::zero(), 1.0 / PI);
+    }
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+    return (
+        Vector3::new(r * theta.cos(), r * theta.sin(), 0.0),
+        1.0 / PI,
+    );
+}
+
+// Direction with density proportional to its cosine with +z, by Malley's
+// method of projecting the concentric disk onto the hemisphere.
+pub fn cosine_hemisphere(u: (f64, f64)) -> (Vector3, f64) {
+    let (d, _) = concentric_disk(u);
+    let z = (1.0 - d.x * d.x - d.y * d.y).max(0.0).sqrt();
+    return (Vector3::new(d.x, d.y, z), cosine_hemisphere_pdf(z));
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    return cos_theta.max(0.0) / PI;
+}
+
+pub fn uniform_sphere(u: (f64, f64)) -> (Vector3, f64) {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    return (
+        Vector3::new(r * phi.cos(), r * phi.sin(), z),
+        1.0 / (4.0 * PI),
+    );
+}
+
+// Uniform direction within `cos_theta_max` of +z.
+pub fn uniform_cone(u: (f64, f64), cos_theta_max: f64) -> (Vector3, f64) {
+    let cos_theta = 1.0 - u.0 * (1.0 - cos_theta_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    return (
+        Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta),
+        uniform_cone_pdf(cos_theta_max),
+    );
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f64) -> f64 {
+    return 1.0 / (2.0 * PI * (1.0 - cos_theta_max));
+}
+
+// Solid angle the triangle `vertices` subtends at `p`, from Van Oosterom
+// and Strackee's formula.
+pub fn spherical_triangle_area(vertices: [Vector3; 3], p: Vector3) -> f64 {
+    let [a, b, c] = vertices.map(|v| (v - p).unit());
+    let numerator = Vector3::dot(a, Vector3::cross(b, c)).abs();
+    let denominator = 1.0 + Vector3::dot(a, b) + Vector3::dot(a, c) + Vector3::dot(b, c);
+    return 2.0 * numerator.atan2(denominator);
+}
+
+// Arvo's uniform sampling of the solid angle of the triangle `vertices`
+// seen from `p`. Returns a world space direction, or nothing if the triangle
+// is degenerate as seen from `p`.
+pub fn spherical_triangle(
+    vertices: [Vector3; 3],
+    p: Vector3,
+    u: (f64, f64),
+) -> Option<(Vector3, f64)> {
+    let [a, b, c] = vertices.map(|v| (v - p).unit());
+    let n_ab = Vector3::cross(a, b);
+    let n_bc = Vector3::cross(b, c);
+    let n_ca = Vector3::cross(c, a);
+    if n_ab.near_zero() || n_bc.near_zero() || n_ca.near_zero() {
+        return None;
+    }
+    let (n_ab, n_bc, n_ca) = (n_ab.unit(), n_bc.unit(), n_ca.unit());
+
+    // Angles at the corners of the spherical triangle, their excess over pi
+    // is its area.
+    let alpha = angle_between(n_ab, -n_ca);
+    let beta = angle_between(n_bc, -n_ab);
+    let gamma = angle_between(n_ca, -n_bc);
+    let area = alpha + beta + gamma - PI;
+    if area <= 0.0 {
+        return None;
+    }
+
+    // Pick the sub-triangle with area `u.0 * area`, which fixes its third
+    // corner `c_prime` on the arc from `a` to `c`.
+    let area_prime = PI + u.0 * area;
+    let (sin_alpha, cos_alpha) = alpha.sin_cos();
+    let sin_phi = area_prime.sin() * cos_alpha - area_prime.cos() * sin_alpha;
+    let cos_phi = area_prime.cos() * cos_alpha + area_prime.sin() * sin_alpha;
+    let k1 = cos_phi + cos_alpha;
+    let k2 = sin_phi - sin_alpha * Vector3::dot(a, b);
+    let cos_b_prime = ((k2 + (k2 * cos_phi - k1 * sin_phi) * cos_alpha)
+        / ((k2 * sin_phi + k1 * cos_phi) * sin_alpha))
+        .clamp(-1.0, 1.0);
+    let sin_b_prime = (1.0 - cos_b_prime * cos_b_prime).max(0.0).sqrt();
+    let c_prime = a * cos_b_prime + gram_schmidt(c, a) * sin_b_prime;
+
+    // Then a point on the arc from `b` to `c_prime`.
+    let cos_theta = 1.0 - u.1 * (1.0 - Vector3::dot(c_prime, b));
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let direction = b * cos_theta + gram_schmidt(c_prime, b) * sin_theta;
+    return Some((direction.unit(), 1.0 / area));
+}
+
+// Angle between two unit vectors, accurate for nearly parallel ones.
+fn angle_between(v1: Vector3, v2: Vector3) -> f64 {
+    if Vector3::dot(v1, v2) < 0.0 {
+        return PI - 2.0 * ((v1 + v2).length() / 2.0).min(1.0).asin();
+    }
+    return 2.0 * ((v2 - v1).length() / 2.0).min(1.0).asin();
+}
+
+// Unit part of `v` orthogonal to the unit vector `w`.
+fn gram_schmidt(v: Vector3, w: Vector3) -> Vector3 {
+    let orthogonal = v - w * Vector3::dot(v, w);
+    if orthogonal.near_zero() {
+        return Vector3::zero();
+    }
+    return orthogonal.unit();
+}
+
+// Heitz's sampling of the GGX normals visible from `wo`, for a surface with
+// roughness `alpha_x` and `alpha_y` along x and y. Returns the microfacet
+// normal and its density, D_wo(h) = G1(wo) max(0, wo.h) D(h) / wo.z.
+#[allow(dead_code)]
+pub fn ggx_visible_normal(
+    wo: Vector3,
+    alpha_x: f64,
+    alpha_y: f64,
+    u: (f64, f64),
+) -> (Vector3, f64) {
+    // Stretch the view direction to the hemisphere configuration.
+    let vh = Vector3::new(alpha_x * wo.x, alpha_y * wo.y, wo.z).unit();
+    let length_squared = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if length_squared > 0.0 {
+        Vector3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = Vector3::cross(vh, t1);
+
+    // Uniform point on the projected half disk.
+    let r = u.0.sqrt();
+    let phi = 2.0 * PI * u.1;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+    let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+    // Unstretch back to the ellipsoid configuration.
+    let h = Vector3::new(alpha_x * nh.x, alpha_y * nh.y, nh.z.max(1e-6)).unit();
+    let pdf =
+        ggx_g1(wo, alpha_x, alpha_y) * Vector3::dot(wo, h).max(0.0) * ggx_d(h, alpha_x, alpha_y)
+            / wo.z;
+    return (h, pdf);
+}
+
+// GGX distribution of microfacet normals `h`.
+#[allow(dead_code)]
+pub fn ggx_d(h: Vector3, alpha_x: f64, alpha_y: f64) -> f64 {
+    if h.z <= 0.0 {
+        return 0.0;
+    }
+    let e = (h.x / alpha_x).powi(2) + (h.y / alpha_y).powi(2) + h.z * h.z;
+    return 1.0 / (PI * alpha_x * alpha_y * e * e);
+}
+
+// Smith masking of GGX microfacets seen from `w`.
+#[allow(dead_code)]
+pub fn ggx_g1(w: Vector3, alpha_x: f64, alpha_y: f64) -> f64 {
+    if w.z <= 0.0 {
+        return 0.0;
+    }
+    let tan2 = ((alpha_x * w.x).powi(2) + (alpha_y * w.y).powi(2)) / (w.z * w.z);
+    let lambda = ((1.0 + tan2).sqrt() - 1.0) / 2.0;
+    return 1.0 / (1.0 + lambda);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::mix_seed;
+
+    const COUNT: u64 = 400_000;
+
+    // Deterministic uniform samples in [0, 1)^2.
+    fn uniform(i: u64) -> (f64, f64) {
+        let unit = |bits: u64| (bits >> 11) as f64 / (1u64 << 53) as f64;
+        return (unit(mix_seed(i, 1)), unit(mix_seed(i, 2)));
+    }
+
+    // Monte Carlo estimate of the integral of `f` over the domain of `warp`,
+    // weighting each sample by its inverse density.
+    fn integrate(warp: impl Fn((f64, f64)) -> (Vector3, f64), f: impl Fn(Vector3) -> f64) -> f64 {
+        let sum: f64 = (0..COUNT)
+            .map(|i| {
+                let (x, pdf) = warp(uniform(i));
+                return f(x) / pdf;
+            })
+            .sum();
+        return sum / COUNT as f64;
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance * expected.abs(),
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn concentric_disk_is_uniform_over_the_unit_disk() {
+        assert_close(integrate(concentric_disk, |_| 1.0), PI, 1e-9);
+        // The disk of radius one half holds a quarter of the area.
+        let inner = integrate(concentric_disk, |p| (p.length() < 0.5) as u8 as f64);
+        assert_close(inner, PI / 4.0, 0.01);
+        assert!((0..COUNT).all(|i| concentric_disk(uniform(i)).0.length() <= 1.0 + 1e-12));
+    }
+
+    #[test]
+    fn cosine_hemisphere_matches_its_density() {
+        for i in 0..1000 {
+            let (w, pdf) = cosine_hemisphere(uniform(i));
+            assert!(w.z >= 0.0 && (w.length() - 1.0).abs() < 1e-9);
+            assert_close(pdf, cosine_hemisphere_pdf(w.z), 1e-12);
+        }
+        // Integral of cos^2 over the hemisphere.
+        assert_close(
+            integrate(cosine_hemisphere, |w| w.z * w.z),
+            2.0 * PI / 3.0,
+            0.01,
+        );
+    }
+
+    #[test]
+    fn uniform_sphere_matches_its_density() {
+        assert_close(
+            integrate(uniform_sphere, |w| w.z * w.z),
+            4.0 * PI / 3.0,
+            0.01,
+        );
+        let upper = integrate(uniform_sphere, |w| (w.x > 0.0) as u8 as f64);
+        assert_close(upper, 2.0 * PI, 0.01);
+    }
+
+    #[test]
+    fn uniform_cone_matches_its_density() {
+        let cos_theta_max: f64 = 0.6;
+        let warp = |u| uniform_cone(u, cos_theta_max);
+        assert!((0..COUNT).all(|i| warp(uniform(i)).0.z >= cos_theta_max - 1e-12));
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        assert_close(integrate(warp, |_| 1.0), solid_angle, 1e-9);
+        // Integral of cos over the cone.
+        let expected = PI * (1.0 - cos_theta_max * cos_theta_max);
+        assert_close(integrate(warp, |w| w.z), expected, 0.01);
+    }
+
+    // Whether the direction `w` lies within the spherical triangle spanned by
+    // the directions `corners`.
+    fn inside(w: Vector3, corners: [Vector3; 3]) -> bool {
+        return (0..3).all(|i| {
+            let (a, b, c) = (corners[i], corners[(i + 1) % 3], corners[(i + 2) % 3]);
+            let n = Vector3::cross(a, b);
+            return Vector3::dot(w, n) * Vector3::dot(c, n) >= -1e-12;
+        });
+    }
+
+    #[test]
+    fn spherical_triangle_is_uniform_over_its_solid_angle() {
+        let p = Vector3::new(0.2, -0.3, 0.1);
+        let vertices = [
+            Vector3::new(1.0, 0.0, 2.0),
+            Vector3::new(-1.5, 0.5, 1.0),
+            Vector3::new(0.3, 2.0, 1.5),
+        ];
+        let area = spherical_triangle_area(vertices, p);
+        let warp = |u| spherical_triangle(vertices, p, u).unwrap();
+        assert_close(integrate(warp, |_| 1.0), area, 1e-9);
+
+        // Every direction falls within the triangle, and the half of it cut
+        // off at the middle of an edge gets its share of the samples.
+        let corners = vertices.map(|v| (v - p).unit());
+        let middle = (vertices[1] + vertices[2]) / 2.0;
+        let half = [corners[0], corners[1], (middle - p).unit()];
+        let half_area = spherical_triangle_area([vertices[0], vertices[1], middle], p);
+        assert!((0..COUNT).all(|i| inside(warp(uniform(i)).0, corners)));
+        let in_half = integrate(warp, |w| inside(w, half) as u8 as f64);
+        assert_close(in_half, half_area, 0.01);
+    }
+
+    #[test]
+    fn ggx_distribution_is_normalized() {
+        // Projected microfacet area adds up to the macro surface.
+        let (alpha_x, alpha_y) = (0.3, 0.6);
+        let projected = integrate(uniform_sphere, |h| ggx_d(h, alpha_x, alpha_y) * h.z);
+        assert_close(projected, 1.0, 0.01);
+    }
+
+    #[test]
+    fn ggx_visible_normals_match_their_density() {
+        let (alpha_x, alpha_y) = (0.3, 0.6);
+        let wo = Vector3::new(0.5, 0.2, 0.8).unit();
+        let visible = |h: Vector3| {
+            return ggx_g1(wo, alpha_x, alpha_y)
+                * Vector3::dot(wo, h).max(0.0)
+                * ggx_d(h, alpha_x, alpha_y)
+                / wo.z;
+        };
+        assert_close(integrate(uniform_sphere, visible), 1.0, 0.01);
+
+        // The returned density is the one above, and the normals are spread
+        // by it: averages over the samples match integrals against it.
+        let mut mean = Vector3::zero();
+        for i in 0..COUNT {
+            let (h, pdf) = ggx_visible_normal(wo, alpha_x, alpha_y, uniform(i));
+            assert!(h.z > 0.0 && Vector3::dot(wo, h) >= -1e-9);
+            if i < 1000 {
+                assert_close(pdf, visible(h), 1e-9);
+            }
+            mean += h;
+        }
+        mean /= COUNT as f64;
+        let expected = |axis: usize| integrate(uniform_sphere, |h| h[axis] * visible(h));
+        for axis in 0..3 {
+            assert!(
+                (mean[axis] - expected(axis)).abs() < 0.01,
+                "axis {}: {} against {}",
+                axis,
+                mean[axis],
+                expected(axis)
+            );
+        }
+    }
+}
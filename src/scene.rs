@@ -0,0 +1,779 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rand::{rngs::OsRng, Rng};
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::{
+    aov::{Aov, MaterialIds},
+    budget::TimeLimit,
+    camera::Camera,
+    filter::Filter,
+    hittable::{
+        bvh::BvhNode, hittable_list::HittableList, quad::make_box, quad::Quad, sphere::Sphere,
+        tagged::Tagged, triangle::Triangle, Hittable,
+    },
+    integrator::IntegratorKind,
+    materials::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    math::hash_bytes,
+    obj::load_obj,
+    output::{ColorTransform, ExrPrecision, ImageFormat},
+    ray::Background,
+    sampler::SamplerKind,
+    tonemap::ToneMap,
+    vec::Vector3,
+};
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u16,
+    pub integrator: IntegratorKind,
+    pub max_depth: u32,
+    pub roulette_depth: u32,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    pub output: PathBuf,
+    pub seed: Option<u64>,
+    pub transform: ColorTransform,
+    pub tone_map: ToneMap,
+    pub exposure: f64,
+    pub exr_precision: ExrPrecision,
+    pub aovs: Vec<Aov>,
+    pub denoise: bool,
+    pub save_noisy: bool,
+    pub noise_threshold: Option<f64>,
+    pub min_samples: u16,
+    pub checkpoint_interval: u64,
+    pub time_limit: Option<TimeLimit>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 3440,
+            height: (3440.0 / (21.0 / 9.0)) as u32,
+            samples_per_pixel: 100,
+            integrator: IntegratorKind::default(),
+            max_depth: 5,
+            roulette_depth: 3,
+            filter: Filter::default(),
+            sampler: SamplerKind::default(),
+            output: PathBuf::from("./image.png"),
+            seed: None,
+            transform: ColorTransform::default(),
+            tone_map: ToneMap::default(),
+            exposure: 0.0,
+            exr_precision: ExrPrecision::default(),
+            aovs: vec![],
+            denoise: false,
+            save_noisy: false,
+            noise_threshold: None,
+            min_samples: 16,
+            checkpoint_interval: 0,
+            time_limit: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f64 {
+        return self.width as f64 / self.height as f64;
+    }
+
+    pub fn seed(&self) -> u64 {
+        return self.seed.unwrap_or(0);
+    }
+
+    // Most samples a pixel may take, zero samples per pixel lifts the limit.
+    pub fn sample_limit(&self) -> u32 {
+        if self.samples_per_pixel == 0 {
+            return u32::MAX;
+        }
+        return self.samples_per_pixel as u32;
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        return ImageFormat::from_path(&self.output).unwrap_or(ImageFormat::Png);
+    }
+
+    // `image.png` is checkpointed to `image.checkpoint`.
+    pub fn checkpoint_path(&self) -> PathBuf {
+        return self.output.with_extension("checkpoint");
+    }
+
+    // Identifies what the samples of a render depend on besides the seed and
+    // image size: the `scene` description and the settings that change how
+    // each sample is taken. Checkpoints only resume renders with the same
+    // fingerprint. The sample count is left out so resumes can add samples,
+    // except with stratified sampling, whose strata follow from it.
+    fn fingerprint(&self, scene: &str) -> u64 {
+        let strata = match self.sampler {
+            SamplerKind::Stratified => self.samples_per_pixel,
+            _ => 0,
+        };
+        let settings = format!(
+            "{:?} {} {} {:?} {:?} {}",
+            self.integrator, self.max_depth, self.roulette_depth, self.filter, self.sampler, strata
+        );
+        return hash_bytes(format!("{}\n{}", scene, settings).as_bytes());
+    }
+
+    // Applies command line overrides and picks a seed if none was given, so
+    // the seed of every render can be reported and reproduced.
+    fn finish(&mut self, overrides: &RenderOverrides) -> Result<(), String> {
+        let aspect_ratio = self.aspect_ratio();
+        match (overrides.width, overrides.height) {
+            (Some(width), Some(height)) => {
+                self.width = width;
+                self.height = height;
+            }
+            (Some(width), None) => {
+                self.width = width;
+                self.height = (width as f64 / aspect_ratio).round() as u32;
+            }
+            (None, Some(height)) => {
+                self.height = height;
+                self.width = (height as f64 * aspect_ratio).round() as u32;
+            }
+            (None, None) => {}
+        }
+        if let Some(samples_per_pixel) = overrides.samples_per_pixel {
+            self.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(integrator) = overrides.integrator {
+            self.integrator = integrator;
+        }
+        if let Some(max_depth) = overrides.max_depth {
+            self.max_depth = max_depth;
+        }
+        if let Some(roulette_depth) = overrides.roulette_depth {
+            self.roulette_depth = roulette_depth;
+        }
+        if let Some(filter) = overrides.filter {
+            self.filter = filter;
+        }
+        if let Some(sampler) = overrides.sampler {
+            self.sampler = sampler;
+        }
+        if let Some(output) = &overrides.output {
+            self.output = output.clone();
+        }
+        if let Some(seed) = overrides.seed {
+            self.seed = Some(seed);
+        }
+        if let Some(transform) = overrides.transform {
+            self.transform = transform;
+        }
+        if let Some(tone_map) = overrides.tone_map {
+            self.tone_map = tone_map;
+        }
+        if let Some(exposure) = overrides.exposure {
+            self.exposure = exposure;
+        }
+        if let Some(exr_precision) = overrides.exr_precision {
+            self.exr_precision = exr_precision;
+        }
+        if let Some(aovs) = &overrides.aovs {
+            self.aovs = aovs.clone();
+        }
+        if let Some(denoise) = overrides.denoise {
+            self.denoise = denoise;
+        }
+        if let Some(save_noisy) = overrides.save_noisy {
+            self.save_noisy = save_noisy;
+        }
+        if let Some(noise_threshold) = overrides.noise_threshold {
+            self.noise_threshold = Some(noise_threshold);
+        }
+        if let Some(min_samples) = overrides.min_samples {
+            self.min_samples = min_samples;
+        }
+        if let Some(checkpoint_interval) = overrides.checkpoint_interval {
+            self.checkpoint_interval = checkpoint_interval;
+        }
+        if let Some(time_limit) = overrides.time_limit {
+            self.time_limit = Some(time_limit);
+        }
+        self.min_samples = match self.samples_per_pixel {
+            0 => self.min_samples.max(1),
+            samples_per_pixel => self.min_samples.clamp(1, samples_per_pixel),
+        };
+        self.seed.get_or_insert_with(|| OsRng.gen());
+
+        // Pixel centers span the camera's view from edge to edge, which
+        // takes at least two of them each way.
+        if self.width < 2 || self.height < 2 {
+            return Err("image width and height must be at least 2 pixels".to_string());
+        }
+        // Without a limit the render has to end some other way. A noise
+        // threshold alone is not enough, a pixel may never reach it.
+        if self.samples_per_pixel == 0 && self.time_limit.is_none() {
+            return Err("samples per pixel can only be unlimited with a time limit".to_string());
+        }
+        if let Some(threshold) = self.noise_threshold {
+            if !(threshold > 0.0 && threshold.is_finite()) {
+                return Err("noise threshold must be positive".to_string());
+            }
+        }
+        if self.save_noisy && !self.denoise {
+            return Err("save_noisy only applies to denoised renders".to_string());
+        }
+        ImageFormat::from_path(&self.output)?;
+        if !self.exposure.is_finite() {
+            return Err("exposure must be a finite number of stops".to_string());
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderOverrides {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples_per_pixel: Option<u16>,
+    pub integrator: Option<IntegratorKind>,
+    pub max_depth: Option<u32>,
+    pub roulette_depth: Option<u32>,
+    pub filter: Option<Filter>,
+    pub sampler: Option<SamplerKind>,
+    pub output: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub transform: Option<ColorTransform>,
+    pub tone_map: Option<ToneMap>,
+    pub exposure: Option<f64>,
+    pub exr_precision: Option<ExrPrecision>,
+    pub aovs: Option<Vec<Aov>>,
+    pub denoise: Option<bool>,
+    pub save_noisy: Option<bool>,
+    pub noise_threshold: Option<f64>,
+    pub min_samples: Option<u16>,
+    pub checkpoint_interval: Option<u64>,
+    pub time_limit: Option<TimeLimit>,
+}
+
+pub struct Scene {
+    pub settings: RenderSettings,
+    pub camera: Camera,
+    pub world: BvhNode<Arc<dyn Hittable>>,
+    pub lights: HittableList,
+    pub background: Background,
+    pub material_ids: MaterialIds,
+    // See `RenderSettings::fingerprint`.
+    pub fingerprint: u64,
+}
+
+impl Scene {
+    pub fn random(overrides: &RenderOverrides) -> Result<Self, String> {
+        let mut settings = RenderSettings::default();
+        settings.finish(overrides)?;
+        let camera = Camera::new(
+            &Vector3::new(13.0, 2.0, 3.0),
+            &Vector3::new(0.0, 0.0, 0.0),
+            &Vector3::new(0.0, 1.0, 0.0),
+            20.0,
+            settings.aspect_ratio(),
+            0.1,
+            10.0,
+        );
+        // The spheres follow from the seed.
+        let fingerprint = settings.fingerprint("random");
+        let (world, materials) = HittableList::random_scene(settings.seed());
+        let mut material_ids = MaterialIds::new();
+        for mat in &materials {
+            material_ids.register(mat);
+        }
+        let objects = world
+            .into_objects()
+            .into_iter()
+            .enumerate()
+            .map(|(index, object)| {
+                Arc::new(Tagged::new(object, index as u32 + 1)) as Arc<dyn Hittable>
+            })
+            .collect();
+        return Ok(Self {
+            settings,
+            camera,
+            world: BvhNode::new(objects),
+            lights: HittableList::new(),
+            background: Background::Sky,
+            material_ids,
+            fingerprint,
+        });
+    }
+
+    pub fn load(path: &Path, overrides: &RenderOverrides) -> Result<Self, SceneError> {
+        let source = fs::read_to_string(path).map_err(|err| SceneError {
+            path: path.to_path_buf(),
+            line: None,
+            message: err.to_string(),
+        })?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        return Scene::parse(&source, directory, overrides).map_err(|(offset, message)| {
+            SceneError {
+                path: path.to_path_buf(),
+                line: offset.map(|offset| line_of(&source, offset)),
+                message,
+            }
+        });
+    }
+
+    // Errors carry the byte offset they refer to, if known, so `load` can
+    // turn them into line numbers.
+    fn parse(
+        source: &str,
+        directory: &Path,
+        overrides: &RenderOverrides,
+    ) -> Result<Self, ParseError> {
+        let file: SceneFile = toml::from_str(source)
+            .map_err(|err| (err.span().map(|span| span.start), err.message().to_string()))?;
+
+        let mut settings = file.render;
+        settings
+            .finish(overrides)
+            .map_err(|message| (None, message))?;
+        // Everything but the `[render]` section describes the scene. It is
+        // hashed in a normalized form, comments and layout do not matter.
+        let mut description: toml::Table =
+            toml::from_str(source).map_err(|err| (None, err.to_string()))?;
+        description.remove("render");
+        let fingerprint = settings.fingerprint(&description.to_string());
+
+        let camera_def = file.camera;
+        let lookfrom = vector(camera_def.lookfrom);
+        let lookat = vector(camera_def.lookat);
+        let camera = Camera::new(
+            &lookfrom,
+            &lookat,
+            &vector(camera_def.vup),
+            camera_def.vfov,
+            settings.aspect_ratio(),
+            camera_def.aperture,
+            camera_def
+                .focus_dist
+                .unwrap_or_else(|| (lookfrom - lookat).length()),
+        );
+
+        let background = match file.background {
+            None => Background::Sky,
+            Some(def) => match def.as_ref() {
+                BackgroundDef::Color(color) => Background::Solid(vector(*color)),
+                BackgroundDef::Named(name) if name == "sky" => Background::Sky,
+                BackgroundDef::Named(name) => {
+                    return Err((
+                        Some(def.span().start),
+                        format!("unknown background '{}'", name),
+                    ))
+                }
+            },
+        };
+
+        let mut materials: BTreeMap<String, (Arc<dyn Material>, bool)> = BTreeMap::new();
+        let mut material_ids = MaterialIds::new();
+        for (name, def) in file.materials {
+            let (mat, emissive) = def.build()?;
+            material_ids.register(&mat);
+            materials.insert(name, (mat, emissive));
+        }
+
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        let objects = file.objects.into_iter().map(|def| (def, false));
+        let light_objects = file.lights.into_iter().map(|def| (def, true));
+        for (index, (def, forced_light)) in objects.chain(light_objects).enumerate() {
+            let object = def.build(
+                &materials,
+                directory,
+                forced_light,
+                &mut material_ids,
+                &mut lights,
+            )?;
+            world.add(Arc::new(Tagged::new(object, index as u32 + 1)));
+        }
+
+        if world.is_empty() {
+            return Err((None, "scene contains no objects".to_string()));
+        }
+
+        return Ok(Self {
+            settings,
+            camera,
+            world: BvhNode::new(world.into_objects()),
+            lights,
+            background,
+            material_ids,
+            fingerprint,
+        });
+    }
+}
+
+// Byte offset the error refers to, if known, and its message.
+type ParseError = (Option<usize>, String);
+
+fn line_of(source: &str, offset: usize) -> usize {
+    return source[..offset.min(source.len())].matches('\n').count() + 1;
+}
+
+fn vector(v: [f64; 3]) -> Vector3 {
+    return Vector3::new(v[0], v[1], v[2]);
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    render: RenderSettings,
+    camera: CameraDef,
+    background: Option<Spanned<BackgroundDef>>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDef>,
+    #[serde(default)]
+    objects: Vec<ObjectDef>,
+    #[serde(default)]
+    lights: Vec<ObjectDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    #[serde(default = "default_vfov")]
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    focus_dist: Option<f64>,
+}
+
+fn default_vup() -> [f64; 3] {
+    return [0.0, 1.0, 0.0];
+}
+
+fn default_vfov() -> f64 {
+    return 40.0;
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "\"sky\" or an [r, g, b] color")]
+enum BackgroundDef {
+    Named(String),
+    Color([f64; 3]),
+}
+
+// Materials are a flat table for the same reason as objects, see below.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDef {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    albedo: Option<[f64; 3]>,
+    fuzz: Option<f64>,
+    ir: Option<f64>,
+    emit: Option<[f64; 3]>,
+}
+
+impl MaterialDef {
+    fn present_fields(&self) -> Vec<&'static str> {
+        let fields = [
+            ("albedo", self.albedo.is_some()),
+            ("fuzz", self.fuzz.is_some()),
+            ("ir", self.ir.is_some()),
+            ("emit", self.emit.is_some()),
+        ];
+        return fields
+            .iter()
+            .filter(|(_, present)| *present)
+            .map(|(name, _)| *name)
+            .collect();
+    }
+
+    // Also tells whether the material emits light.
+    fn build(self) -> Result<(Arc<dyn Material>, bool), ParseError> {
+        let kind = self.kind.as_ref().as_str();
+        let at = Some(self.kind.span().start);
+
+        let allowed: &[&str] = match kind {
+            "lambertian" => &["albedo"],
+            "metal" => &["albedo", "fuzz"],
+            "dielectric" => &["ir"],
+            "diffuse_light" => &["emit"],
+            _ => return Err((at, format!("unknown material type '{}'", kind))),
+        };
+        for field in self.present_fields() {
+            if !allowed.contains(&field) {
+                return Err((at, format!("field `{}` is not valid for a {}", field, kind)));
+            }
+        }
+
+        let missing = |field: &str| (at, format!("{} is missing field `{}`", kind, field));
+        return match kind {
+            "lambertian" => {
+                let albedo = self.albedo.ok_or_else(|| missing("albedo"))?;
+                Ok((Arc::new(Lambertian::new(vector(albedo))), false))
+            }
+            "metal" => {
+                let albedo = self.albedo.ok_or_else(|| missing("albedo"))?;
+                let fuzz = self.fuzz.unwrap_or(0.0);
+                Ok((Arc::new(Metal::new(vector(albedo), fuzz)), false))
+            }
+            "dielectric" => {
+                let ir = self.ir.ok_or_else(|| missing("ir"))?;
+                Ok((Arc::new(Dielectric::new(ir)), false))
+            }
+            _ => {
+                let emit = self.emit.ok_or_else(|| missing("emit"))?;
+                Ok((Arc::new(DiffuseLight::new(vector(emit))), true))
+            }
+        };
+    }
+}
+
+// Objects are a flat table rather than a tagged enum so that the `Spanned`
+// values survive deserialization and errors can point at their line. A
+// tagged enum buffers its contents, and its errors point at the table.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectDef {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    center: Option<[f64; 3]>,
+    radius: Option<f64>,
+    q: Option<[f64; 3]>,
+    u: Option<[f64; 3]>,
+    v: Option<[f64; 3]>,
+    min: Option<[f64; 3]>,
+    max: Option<[f64; 3]>,
+    vertices: Option<[[f64; 3]; 3]>,
+    normals: Option<[[f64; 3]; 3]>,
+    path: Option<String>,
+    material: Option<Spanned<String>>,
+    light: Option<bool>,
+}
+
+impl ObjectDef {
+    fn present_fields(&self) -> Vec<&'static str> {
+        let fields = [
+            ("center", self.center.is_some()),
+            ("radius", self.radius.is_some()),
+            ("q", self.q.is_some()),
+            ("u", self.u.is_some()),
+            ("v", self.v.is_some()),
+            ("min", self.min.is_some()),
+            ("max", self.max.is_some()),
+            ("vertices", self.vertices.is_some()),
+            ("normals", self.normals.is_some()),
+            ("path", self.path.is_some()),
+            ("material", self.material.is_some()),
+            ("light", self.light.is_some()),
+        ];
+        return fields
+            .iter()
+            .filter(|(_, present)| *present)
+            .map(|(name, _)| *name)
+            .collect();
+    }
+
+    fn build(
+        self,
+        materials: &BTreeMap<String, (Arc<dyn Material>, bool)>,
+        directory: &Path,
+        forced_light: bool,
+        material_ids: &mut MaterialIds,
+        lights: &mut HittableList,
+    ) -> Result<Arc<dyn Hittable>, ParseError> {
+        let kind = self.kind.as_ref().as_str();
+        let at = Some(self.kind.span().start);
+
+        let allowed: &[&str] = match kind {
+            "sphere" => &["center", "radius", "material", "light"],
+            "quad" => &["q", "u", "v", "material", "light"],
+            "box" => &["min", "max", "material", "light"],
+            "triangle" => &["vertices", "normals", "material", "light"],
+            "obj" => &["path", "material"],
+            _ => return Err((at, format!("unknown object type '{}'", kind))),
+        };
+        for field in self.present_fields() {
+            if !allowed.contains(&field) {
+                return Err((at, format!("field `{}` is not valid for a {}", field, kind)));
+            }
+        }
+
+        let missing = |field: &str| (at, format!("{} is missing field `{}`", kind, field));
+        let lookup = |name: &Option<Spanned<String>>| {
+            let name = name.as_ref().ok_or_else(|| missing("material"))?;
+            return match materials.get(name.as_ref()) {
+                Some((mat, emissive)) => Ok((Arc::clone(mat), *emissive)),
+                None => Err((
+                    Some(name.span().start),
+                    format!("unknown material '{}'", name.as_ref()),
+                )),
+            };
+        };
+
+        let (object, emissive): (Arc<dyn Hittable>, bool) = match kind {
+            "sphere" => {
+                let center = self.center.ok_or_else(|| missing("center"))?;
+                let radius = self.radius.ok_or_else(|| missing("radius"))?;
+                let (mat, emissive) = lookup(&self.material)?;
+                (Arc::new(Sphere::new(vector(center), radius, mat)), emissive)
+            }
+            "quad" => {
+                let q = self.q.ok_or_else(|| missing("q"))?;
+                let u = self.u.ok_or_else(|| missing("u"))?;
+                let v = self.v.ok_or_else(|| missing("v"))?;
+                let (mat, emissive) = lookup(&self.material)?;
+                let quad = Quad::new(vector(q), vector(u), vector(v), mat);
+                (Arc::new(quad), emissive)
+            }
+            "box" => {
+                let min = self.min.ok_or_else(|| missing("min"))?;
+                let max = self.max.ok_or_else(|| missing("max"))?;
+                let (mat, emissive) = lookup(&self.material)?;
+                (Arc::new(make_box(vector(min), vector(max), mat)), emissive)
+            }
+            "triangle" => {
+                let [p0, p1, p2] = self.vertices.ok_or_else(|| missing("vertices"))?;
+                let (mat, emissive) = lookup(&self.material)?;
+                let triangle = match self.normals {
+                    Some([n0, n1, n2]) => Triangle::with_normals(
+                        vector(p0),
+                        vector(p1),
+                        vector(p2),
+                        vector(n0),
+                        vector(n1),
+                        vector(n2),
+                        mat,
+                    ),
+                    None => Triangle::new(vector(p0), vector(p1), vector(p2), mat),
+                };
+                (Arc::new(triangle), emissive)
+            }
+            _ => {
+                if forced_light {
+                    return Err((at, "obj meshes cannot be sampled as lights".to_string()));
+                }
+                let path = self.path.as_ref().ok_or_else(|| missing("path"))?;
+                let default_material = match self.material {
+                    Some(_) => lookup(&self.material)?.0,
+                    None => Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+                };
+                let meshes = load_obj(&directory.join(path), default_material)
+                    .map_err(|err| (at, err.to_string()))?;
+                let mut list = HittableList::new();
+                for mesh in meshes {
+                    material_ids.register(mesh.material());
+                    list.add(Arc::new(mesh));
+                }
+                return Ok(Arc::new(BvhNode::new(list.into_objects())));
+            }
+        };
+
+        // Emissive objects are sampled as lights unless explicitly opted out.
+        if forced_light || self.light.unwrap_or(emissive) {
+            lights.add(Arc::clone(&object));
+        }
+        return Ok(object);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "[camera]\nlookfrom = [0.0, 0.0, 5.0]\nlookat = [0.0, 0.0, 0.0]\n";
+
+    // A sphere made of the material `white`.
+    const SPHERE: &str = "
+[[objects]]
+type = \"sphere\"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = \"white\"
+";
+
+    // Line and message of the error the scene made of `CAMERA` followed by
+    // `rest` fails with.
+    fn error(rest: &str) -> (Option<usize>, String) {
+        let source = format!("{}{}", CAMERA, rest);
+        return match Scene::parse(&source, Path::new(""), &RenderOverrides::default()) {
+            Ok(_) => panic!("scene parsed without error"),
+            Err((offset, message)) => (offset.map(|offset| line_of(&source, offset)), message),
+        };
+    }
+
+    #[test]
+    fn valid_scene_parses() {
+        let source = format!(
+            "{}\n[materials.white]\ntype = \"lambertian\"\nalbedo = [0.7, 0.7, 0.7]\n{}",
+            CAMERA, SPHERE
+        );
+        assert!(Scene::parse(&source, Path::new(""), &RenderOverrides::default()).is_ok());
+    }
+
+    #[test]
+    fn bad_render_value_reports_its_line() {
+        let (line, message) = error("\n[render]\nwidth = 64\nmax_depth = \"deep\"\n");
+        assert_eq!(line, Some(7));
+        assert!(message.contains("invalid type"), "{}", message);
+    }
+
+    #[test]
+    fn bad_material_value_reports_its_line() {
+        let rest = format!(
+            "\n[materials.white]\ntype = \"lambertian\"\n\nalbedo = [0.7, 0.7]\n{}",
+            SPHERE
+        );
+        let (line, message) = error(&rest);
+        assert_eq!(line, Some(8));
+        assert!(message.contains("invalid length 2"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_material_reports_its_line() {
+        let rest = format!(
+            "\n[materials.red]\ntype = \"lambertian\"\nalbedo = [0.7, 0.1, 0.1]\n{}",
+            SPHERE
+        );
+        let (line, message) = error(&rest);
+        assert_eq!(line, Some(13));
+        assert_eq!(message, "unknown material 'white'");
+    }
+
+    #[test]
+    fn unknown_field_reports_its_line() {
+        let rest = format!(
+            "\n[materials.white]\ntype = \"lambertian\"\nalbedo = [0.7, 0.7, 0.7]\ncolour = 1\n{}",
+            SPHERE
+        );
+        let (line, message) = error(&rest);
+        assert_eq!(line, Some(8));
+        assert!(message.contains("unknown field `colour`"), "{}", message);
+    }
+}
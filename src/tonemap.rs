@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::vec::Vector3;
+
+// Maps unbounded scene radiance into [0, 1] before the output transfer
+// function. Every operator returns linear values.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ToneMap {
+    // Leaves the image as is, values above one are clipped on output.
+    #[default]
+    None,
+    // Extended Reinhard on luminance. Luminance `white` maps to one, the
+    // brightest pixel of the image is used when no white point is given.
+    Reinhard(Option<f64>),
+    // Stephen Hill's fit of the ACES reference and output transforms.
+    Aces,
+    // Minimal AgX approximation with the default look.
+    Agx,
+}
+
+impl ToneMap {
+    // Scales `pixels` by 2^`exposure` and applies the operator.
+    pub fn apply(&self, pixels: &[Vector3], exposure: f64) -> Vec<Vector3> {
+        let scale = exposure.exp2();
+        let exposed = pixels.iter().map(|color| *color * scale);
+        return match self {
+            ToneMap::None => exposed.collect(),
+            ToneMap::Reinhard(white) => {
+                let white = white.unwrap_or_else(|| {
+                    pixels
+                        .iter()
+                        .map(|color| luminance(*color * scale))
+                        .fold(0.0, f64::max)
+                });
+                exposed.map(|color| reinhard(color, white)).collect()
+            }
+            ToneMap::Aces => exposed.map(aces).collect(),
+            ToneMap::Agx => exposed.map(agx).collect(),
+        };
+    }
+}
+
+// Accepts `none`, `reinhard`, `reinhard:<white>`, `aces` and `agx`.
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        return match (name, value) {
+            ("none", None) => Ok(ToneMap::None),
+            ("reinhard", None) => Ok(ToneMap::Reinhard(None)),
+            ("reinhard", Some(value)) => match value.parse::<f64>() {
+                Ok(white) if white > 0.0 && white.is_finite() => Ok(ToneMap::Reinhard(Some(white))),
+                _ => Err(format!("invalid white point `{}`", value)),
+            },
+            ("aces", None) => Ok(ToneMap::Aces),
+            ("agx", None) => Ok(ToneMap::Agx),
+            _ => Err(format!(
+                "unknown tone map `{}`, expected none, reinhard, reinhard:<white>, aces or agx",
+                s
+            )),
+        };
+    }
+}
+
+impl TryFrom<String> for ToneMap {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return s.parse();
+    }
+}
+
+pub fn luminance(color: Vector3) -> f64 {
+    return 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+}
+
+fn transform(m: &[[f64; 3]; 3], c: Vector3) -> Vector3 {
+    return Vector3::new(
+        m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+        m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+        m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z,
+    );
+}
+
+fn map_channels(c: Vector3, f: impl Fn(f64) -> f64) -> Vector3 {
+    return Vector3::new(f(c.x), f(c.y), f(c.z));
+}
+
+fn reinhard(color: Vector3, white: f64) -> Vector3 {
+    let l = luminance(color);
+    if l <= 0.0 || white <= 0.0 {
+        return Vector3::zero();
+    }
+    let mapped = l * (1.0 + l / (white * white)) / (1.0 + l);
+    return color * (mapped / l);
+}
+
+const ACES_INPUT: [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+const ACES_OUTPUT: [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+fn aces(color: Vector3) -> Vector3 {
+    let v = transform(&ACES_INPUT, color);
+    let fitted = map_channels(v, |x| {
+        let a = x * (x + 0.0245786) - 0.000090537;
+        let b = x * (0.983729 * x + 0.4329510) + 0.238081;
+        return a / b;
+    });
+    return map_channels(transform(&ACES_OUTPUT, fitted), |x| x.clamp(0.0, 1.0));
+}
+
+const AGX_INSET: [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+
+const AGX_OUTSET: [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+fn agx(color: Vector3) -> Vector3 {
+    let inset = transform(&AGX_INSET, color);
+    // Log2 encoding over the working range, then a polynomial fit of the
+    // sigmoid contrast curve.
+    let curve = map_channels(inset, |x| {
+        let x = (x.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV)
+            / (AGX_MAX_EV - AGX_MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x
+            + 0.4298 * x2
+            + 0.1191 * x
+            - 0.00232;
+    });
+    // The curve produces display encoded values, undo the 2.2 power so the
+    // output transform can encode them again.
+    return map_channels(transform(&AGX_OUTSET, curve), |x| {
+        x.clamp(0.0, 1.0).powf(2.2)
+    });
+}
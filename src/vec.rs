@@ -1,6 +1,6 @@
 use std::ops;
 
-use rand::{rngs::{OsRng}, Rng};
+use rand::Rng;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vector3 {
@@ -20,11 +20,11 @@ impl Vector3 {
     pub fn new(x: f64, y: f64, z: f64) -> Self {
         Self { x, y, z }
     }
-    pub fn random() -> Self {
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
         return Vector3 {
-            x: OsRng.gen(),
-            y: OsRng.gen(),
-            z: OsRng.gen(),
+            x: rng.gen(),
+            y: rng.gen(),
+            z: rng.gen(),
         };
     }
     pub fn inherit(&mut self, new: Vector3) {
@@ -32,11 +32,11 @@ impl Vector3 {
         self.y = new.y;
         self.z = new.z;
     }
-    pub fn random_bound( min: f64, max: f64) -> Self {
+    pub fn random_bound<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Self {
         return Vector3 {
-            x: OsRng.gen_range(min..max),
-            y: OsRng.gen_range(min..max),
-            z: OsRng.gen_range(min..max),
+            x: rng.gen_range(min..max),
+            y: rng.gen_range(min..max),
+            z: rng.gen_range(min..max),
         };
     }
     pub fn length_squared(&self) -> f64 {
@@ -58,6 +58,9 @@ impl Vector3 {
     pub fn unit(&self) -> Self {
         return *self / self.length();
     }
+    pub fn max_component(&self) -> f64 {
+        return self.x.max(self.y).max(self.z);
+    }
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         return (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s);
@@ -171,3 +174,25 @@ impl ops::DivAssign<f64> for Vector3 {
         self.z /= rhs;
     }
 }
+impl ops::Index<usize> for Vector3 {
+    type Output = f64;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vector3 index out of range: {}", index),
+        }
+    }
+}
+impl ops::IndexMut<usize> for Vector3 {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("Vector3 index out of range: {}", index),
+        }
+    }
+}